@@ -0,0 +1,180 @@
+//! Wiring and electrical constraints of common boards that carry a NINA module.
+//!
+//! Each profile is gated behind a `board-*` cargo feature.
+
+use crate::led;
+use crate::types;
+use core::fmt;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct BoardProfile {
+    pub name: &'static str,
+    pub host_pins: HostPins,
+    pub spi_mode: embedded_hal::spi::Mode,
+    pub max_spi_frequency_hz: u32,
+    /// The firmware fork the board ships with.
+    pub firmware_vendor: types::FirmwareVendor,
+    /// The RGB LED hanging off the ESP32, if the board has one.
+    pub led: Option<led::LedPins>,
+    /// ESP32 pins that are broken out for application use.
+    pub gpio_pins: &'static [u8],
+}
+
+/// Host MCU pins connected to the module, named as in the board's pinout.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HostPins {
+    pub cs: &'static str,
+    pub busy: &'static str,
+    pub reset: &'static str,
+    /// Only needed to put the ESP32 into its bootloader; `None` if it isn't connected.
+    pub gpio0: Option<&'static str>,
+}
+
+impl BoardProfile {
+    /// Whether the board lets applications use `pin`.
+    pub fn has_gpio_pin(&self, pin: u8) -> bool {
+        self.gpio_pins.contains(&pin)
+    }
+}
+
+// `embedded_hal::spi::Mode` doesn't implement `Debug`
+impl fmt::Debug for BoardProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use embedded_hal::spi::{Phase, Polarity};
+
+        let mode = match (self.spi_mode.polarity, self.spi_mode.phase) {
+            (Polarity::IdleLow, Phase::CaptureOnFirstTransition) => 0,
+            (Polarity::IdleLow, Phase::CaptureOnSecondTransition) => 1,
+            (Polarity::IdleHigh, Phase::CaptureOnFirstTransition) => 2,
+            (Polarity::IdleHigh, Phase::CaptureOnSecondTransition) => 3,
+        };
+        f.debug_struct("BoardProfile")
+            .field("name", &self.name)
+            .field("host_pins", &self.host_pins)
+            .field("spi_mode", &mode)
+            .field("max_spi_frequency_hz", &self.max_spi_frequency_hz)
+            .field("firmware_vendor", &self.firmware_vendor)
+            .field("led", &self.led)
+            .field("gpio_pins", &self.gpio_pins)
+            .finish()
+    }
+}
+
+#[cfg(feature = "board-nano-33-iot")]
+pub const NANO_33_IOT: BoardProfile = BoardProfile {
+    name: "Arduino Nano 33 IoT",
+    host_pins: HostPins {
+        cs: "PA14",
+        busy: "PA28",
+        reset: "PA08",
+        gpio0: Some("PA27"),
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Arduino,
+    led: None,
+    gpio_pins: &[],
+};
+
+#[cfg(feature = "board-mkr-wifi-1010")]
+pub const MKR_WIFI_1010: BoardProfile = BoardProfile {
+    name: "Arduino MKR WiFi 1010",
+    host_pins: HostPins {
+        cs: "PA14",
+        busy: "PA28",
+        reset: "PB08",
+        gpio0: Some("PA27"),
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Arduino,
+    led: Some(led::LedPins::ARDUINO),
+    gpio_pins: &[],
+};
+
+#[cfg(feature = "board-uno-wifi-rev2")]
+pub const UNO_WIFI_REV2: BoardProfile = BoardProfile {
+    name: "Arduino Uno WiFi Rev2",
+    host_pins: HostPins {
+        cs: "PF2",
+        busy: "PF3",
+        reset: "PF4",
+        gpio0: Some("PA2"),
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Arduino,
+    led: None,
+    gpio_pins: &[],
+};
+
+#[cfg(feature = "board-nano-rp2040-connect")]
+pub const NANO_RP2040_CONNECT: BoardProfile = BoardProfile {
+    name: "Arduino Nano RP2040 Connect",
+    host_pins: HostPins {
+        cs: "GPIO9",
+        busy: "GPIO10",
+        reset: "GPIO3",
+        gpio0: Some("GPIO2"),
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Arduino,
+    led: Some(led::LedPins {
+        red: 27,
+        green: 25,
+        blue: 26,
+        common_anode: false,
+    }),
+    // A4 to A7 on the header
+    gpio_pins: &[34, 35, 36, 39],
+};
+
+#[cfg(feature = "board-airlift-featherwing")]
+pub const AIRLIFT_FEATHERWING: BoardProfile = BoardProfile {
+    name: "Adafruit AirLift FeatherWing",
+    host_pins: HostPins {
+        cs: "D13",
+        busy: "D11",
+        reset: "D12",
+        gpio0: None,
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Adafruit,
+    led: Some(led::LedPins::AIRLIFT),
+    gpio_pins: &[],
+};
+
+/// The breakout is wired by hand, so the host pins are the labels on its header.
+#[cfg(feature = "board-airlift-breakout")]
+pub const AIRLIFT_BREAKOUT: BoardProfile = BoardProfile {
+    name: "Adafruit AirLift Breakout",
+    host_pins: HostPins {
+        cs: "CS",
+        busy: "BUSY",
+        reset: "RST",
+        gpio0: Some("GPIO0"),
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Adafruit,
+    led: Some(led::LedPins::AIRLIFT),
+    gpio_pins: &[],
+};
+
+#[cfg(feature = "board-airlift-shield")]
+pub const AIRLIFT_SHIELD: BoardProfile = BoardProfile {
+    name: "Adafruit AirLift Shield",
+    host_pins: HostPins {
+        cs: "D10",
+        busy: "D7",
+        reset: "D5",
+        gpio0: None,
+    },
+    spi_mode: embedded_hal::spi::MODE_0,
+    max_spi_frequency_hz: 8_000_000,
+    firmware_vendor: types::FirmwareVendor::Adafruit,
+    led: Some(led::LedPins::AIRLIFT),
+    gpio_pins: &[],
+};
@@ -12,6 +12,7 @@ pub enum Command {
     SetApPassphraseCmd = 0x19,
     SetDebugCmd = 0x1A,
     GetTemperatureCmd = 0x1B,
+    GetReasonCodeCmd = 0x1F,
 
     GetConnStatusCmd = 0x20,
     GetIpaddrCmd = 0x21,
@@ -45,6 +46,8 @@ pub enum Command {
     GetSocketCmd = 0x3F,
 
     // All command with DATA_FLAG 0x40 send a 16bit Len
+    SetClientCertCmd = 0x40,
+    SetCertKeyCmd = 0x41,
     SendDataTcpCmd = 0x44,
     GetDatabufTcpCmd = 0x45,
     InsertDatabufCmd = 0x46,
@@ -53,4 +56,6 @@ pub enum Command {
     SetPinMode = 0x50,
     SetDigitalWrite = 0x51,
     SetAnalogWrite = 0x52,
+    SetDigitalRead = 0x53,
+    SetAnalogRead = 0x54,
 }
@@ -5,22 +5,52 @@ pub enum Error<E> {
     Transport(E),
     SetNetwork,
     SetPassphrase,
+    SetApNetwork,
+    SetApPassphrase,
     SetKey,
     SetIpConfig,
     SetDnsConfig,
     SetHostname,
+    SetDebug,
+    SetClientCert,
+    SetCertKey,
     Disconnect,
     ReqHostByName,
     StartScanNetworks,
     StartClientByIp,
+    StartClientByHost,
+    TlsHandshake,
+    StartServer,
     StopClient,
     CheckDataSent,
+    InsertDataBuf,
+    SendUdpData,
     PinMode,
     DigitalWrite,
     AnalogWrite,
-    ConnectionFailure(types::ConnectionState),
+    InvalidPin(u8),
+    TcpStateTimeout(types::TcpState),
+    ConnectTimeout(types::TcpState),
+    ConnectRefused(types::TcpState),
+    ConnectionFailure(types::ConnectionState, Option<types::DisconnectReason>),
+    Ping(types::PingFailure),
     BadConnectionStatus(num_enum::TryFromPrimitiveError<types::ConnectionState>),
     BadEncryptionType(num_enum::TryFromPrimitiveError<types::EncryptionType>),
     BadTcpState(num_enum::TryFromPrimitiveError<types::TcpState>),
     DataTooLong,
+    NoBuffer,
+    NoSocketAvailable,
+    InvalidPem,
+    BadFirmwareVersion,
+    Unsupported(types::Capability),
+    NotConfigured,
+    ClientInvalidated,
+    WrongWifi,
+    NotConnected,
+    NotMulticast(no_std_net::Ipv4Addr),
+    InvalidSocketState,
+    Ipv6Unsupported,
+    ReverseDnsUnsupported,
+    MixedModeUnsupported,
+    AuthMethodUnsupported,
 }
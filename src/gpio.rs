@@ -0,0 +1,247 @@
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::Wifi;
+use core::cell;
+use embedded_hal::digital::v2 as digital;
+
+/// Whether `pin` is an ESP32 GPIO that can drive an output.
+///
+/// GPIOs 6 to 11 are wired to the module's flash and 1 and 3 are its UART. The firmware talks to
+/// the host over 5, 12, 14, 18, 23 and 33, so driving those would break the SPI link.
+pub fn is_output_pin(pin: u8) -> bool {
+    matches!(pin, 0 | 2 | 4 | 13 | 15..=17 | 19 | 21 | 22 | 25..=27 | 32)
+}
+
+/// Whether `pin` is an ESP32 GPIO that can be read, including the input-only pins 34 to 39.
+pub fn is_input_pin(pin: u8) -> bool {
+    matches!(pin, 34..=36 | 39) || is_output_pin(pin)
+}
+
+/// Whether `pin` is connected to ADC1, the only ADC that works while WiFi is active.
+pub fn is_analog_input_pin(pin: u8) -> bool {
+    matches!(pin, 32 | 34..=36 | 39)
+}
+
+pub(crate) fn check_output<E>(pin: u8) -> Result<(), error::Error<E>> {
+    check(pin, is_output_pin(pin))
+}
+
+pub(crate) fn check_input<E>(pin: u8) -> Result<(), error::Error<E>> {
+    check(pin, is_input_pin(pin))
+}
+
+pub(crate) fn check_analog_input<E>(pin: u8) -> Result<(), error::Error<E>> {
+    check(pin, is_analog_input_pin(pin))
+}
+
+fn check<E>(pin: u8, valid: bool) -> Result<(), error::Error<E>> {
+    if valid {
+        Ok(())
+    } else {
+        Err(error::Error::InvalidPin(pin))
+    }
+}
+
+/// An ESP32 pin configured as a digital output, usable wherever an embedded-hal `OutputPin` is
+/// expected.
+///
+/// Pin handles share the `Wifi` through a `RefCell`, so several of them can coexist with other
+/// users of the same module as long as no borrow is held across calls.
+#[derive(Debug)]
+pub struct Output<'a, T> {
+    wifi: &'a cell::RefCell<Wifi<T>>,
+    pin: u8,
+    high: bool,
+}
+
+#[derive(Debug)]
+pub struct Input<'a, T> {
+    wifi: &'a cell::RefCell<Wifi<T>>,
+    pin: u8,
+}
+
+/// An ESP32 pin driven by the firmware's 8-bit PWM.
+///
+/// `embedded_hal::PwmPin` has no way to report errors, so the last failed write is kept around
+/// for `take_error`.
+#[derive(Debug)]
+pub struct Pwm<'a, T>
+where
+    T: transport::Transport,
+{
+    wifi: &'a cell::RefCell<Wifi<T>>,
+    pin: u8,
+    duty: u8,
+    enabled: bool,
+    error: Option<error::Error<T::Error>>,
+}
+
+impl<'a, T> Output<'a, T>
+where
+    T: transport::Transport,
+{
+    pub fn new(wifi: &'a cell::RefCell<Wifi<T>>, pin: u8) -> Result<Self, error::Error<T::Error>> {
+        wifi.borrow_mut().pin_mode(pin, types::PinMode::Output)?;
+        let high = false;
+        Ok(Self { wifi, pin, high })
+    }
+
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+}
+
+impl<'a, T> Input<'a, T>
+where
+    T: transport::Transport,
+{
+    pub fn new(
+        wifi: &'a cell::RefCell<Wifi<T>>,
+        pin: u8,
+        pull_up: bool,
+    ) -> Result<Self, error::Error<T::Error>> {
+        let mode = if pull_up {
+            types::PinMode::InputPullup
+        } else {
+            types::PinMode::Input
+        };
+        wifi.borrow_mut().pin_mode(pin, mode)?;
+        Ok(Self { wifi, pin })
+    }
+
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+}
+
+impl<'a, T> Pwm<'a, T>
+where
+    T: transport::Transport,
+{
+    pub fn new(wifi: &'a cell::RefCell<Wifi<T>>, pin: u8) -> Result<Self, error::Error<T::Error>> {
+        wifi.borrow_mut().pin_mode(pin, types::PinMode::Output)?;
+        let duty = 0;
+        let enabled = false;
+        let error = None;
+        Ok(Self {
+            wifi,
+            pin,
+            duty,
+            enabled,
+            error,
+        })
+    }
+
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    pub fn take_error(&mut self) -> Option<error::Error<T::Error>> {
+        self.error.take()
+    }
+
+    fn write(&mut self, duty: u8) {
+        if let Err(err) = self.wifi.borrow_mut().analog_write(self.pin, duty) {
+            log::warn!("PWM write to pin {} failed", self.pin);
+            self.error = Some(err);
+        }
+    }
+}
+
+impl<'a, T> digital::OutputPin for Output<'a, T>
+where
+    T: transport::Transport,
+{
+    type Error = error::Error<T::Error>;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.wifi.borrow_mut().digital_write(self.pin, false)?;
+        self.high = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.wifi.borrow_mut().digital_write(self.pin, true)?;
+        self.high = true;
+        Ok(())
+    }
+}
+
+impl<'a, T> digital::StatefulOutputPin for Output<'a, T>
+where
+    T: transport::Transport,
+{
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.high)
+    }
+
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.high)
+    }
+}
+
+impl<'a, T> digital::toggleable::Default for Output<'a, T> where T: transport::Transport {}
+
+impl<'a, T> digital::InputPin for Input<'a, T>
+where
+    T: transport::Transport,
+{
+    type Error = error::Error<T::Error>;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.wifi.borrow_mut().digital_read(self.pin)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
+
+impl<'a, T> embedded_hal::PwmPin for Pwm<'a, T>
+where
+    T: transport::Transport,
+{
+    type Duty = u8;
+
+    fn disable(&mut self) {
+        self.write(0);
+        self.enabled = false;
+    }
+
+    fn enable(&mut self) {
+        self.write(self.duty);
+        self.enabled = true;
+    }
+
+    fn get_duty(&self) -> Self::Duty {
+        self.duty
+    }
+
+    fn get_max_duty(&self) -> Self::Duty {
+        u8::MAX
+    }
+
+    fn set_duty(&mut self, duty: Self::Duty) {
+        self.duty = duty;
+        if self.enabled {
+            self.write(duty);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_pins_are_reserved() {
+        for &pin in &[1, 3, 5, 12, 14, 18, 23, 33] {
+            assert!(!is_output_pin(pin), "pin {}", pin);
+            assert!(!is_input_pin(pin), "pin {}", pin);
+            assert!(!is_analog_input_pin(pin), "pin {}", pin);
+        }
+        assert!(is_output_pin(25));
+        assert!(is_input_pin(34) && !is_output_pin(34));
+    }
+}
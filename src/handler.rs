@@ -9,6 +9,9 @@ use crate::types;
 use core::fmt;
 use core::time;
 
+/// What the firmware returns in place of a socket number when it has none to give.
+const NO_SOCKET: u8 = 255;
+
 #[derive(Debug)]
 pub struct Handler<T> {
     transport: T,
@@ -22,6 +25,11 @@ where
         Self { transport }
     }
 
+    #[cfg(test)]
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
     pub fn get_connection_state(
         &mut self,
     ) -> Result<types::ConnectionState, error::Error<T::Error>> {
@@ -38,6 +46,20 @@ where
         Ok(status)
     }
 
+    pub fn reset(&mut self) -> Result<(), error::Error<T::Error>> {
+        self.transport.reset().map_err(error::Error::Transport)
+    }
+
+    pub fn get_reason_code(&mut self) -> Result<u8, error::Error<T::Error>> {
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(command::Command::GetReasonCodeCmd, &(), &mut recv_params)?;
+
+        let (reason,) = recv_params;
+
+        Ok(reason)
+    }
+
     pub fn delay(&mut self, duration: time::Duration) -> Result<(), error::Error<T::Error>> {
         self.transport
             .delay(duration)
@@ -61,6 +83,16 @@ where
         Ok(result)
     }
 
+    pub fn get_temperature(&mut self) -> Result<types::Temperature, error::Error<T::Error>> {
+        let mut recv_params = (param::Scalar::le(0f32),);
+
+        self.handle_cmd(command::Command::GetTemperatureCmd, &(), &mut recv_params)?;
+
+        let (temperature,) = recv_params;
+
+        Ok(types::Temperature::from_celsius(temperature.into_inner()))
+    }
+
     pub fn get_mac_address(&mut self) -> Result<[u8; 6], error::Error<T::Error>> {
         let send_params = (0u8,);
         let mut recv_params = (arrayvec::ArrayVec::new(),);
@@ -88,6 +120,7 @@ where
         }
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn get_scanned_networks(
         &mut self,
     ) -> Result<arrayvec::ArrayVec<[arrayvec::ArrayVec<[u8; 32]>; 16]>, error::Error<T::Error>>
@@ -203,6 +236,29 @@ where
         Ok(ip.into_inner().into())
     }
 
+    pub fn ping(
+        &mut self,
+        ip: no_std_net::Ipv4Addr,
+        ttl: u8,
+    ) -> Result<time::Duration, error::Error<T::Error>> {
+        use core::convert::TryFrom;
+
+        let send_params = (param::Scalar::be(u32::from(ip)), ttl);
+        let mut recv_params = (param::Scalar::le(0u16),);
+
+        self.handle_cmd(command::Command::PingCmd, &send_params, &mut recv_params)?;
+
+        let (result,) = recv_params;
+        let result = result.into_inner() as i16;
+
+        if result >= 0 {
+            Ok(time::Duration::from_millis(result as u64))
+        } else {
+            let failure = types::PingFailure::try_from(result).unwrap_or(types::PingFailure::Error);
+            Err(error::Error::Ping(failure))
+        }
+    }
+
     pub fn get_network_data(&mut self) -> Result<types::NetworkData, error::Error<T::Error>> {
         let send_params = (0u8,);
         let mut recv_params = (
@@ -230,7 +286,7 @@ where
         socket: types::Socket,
     ) -> Result<types::RemoteData, error::Error<T::Error>> {
         let send_params = (socket.0,);
-        let mut recv_params = (param::Scalar::be(0u32), param::Scalar::be(0u32));
+        let mut recv_params = (param::Scalar::be(0u32), param::Scalar::be(0u16));
 
         self.handle_cmd(
             command::Command::GetRemoteDataCmd,
@@ -286,6 +342,57 @@ where
         }
     }
 
+    pub fn set_ap_network(
+        &mut self,
+        ssid: &[u8],
+        channel: u8,
+    ) -> Result<(), error::Error<T::Error>> {
+        let send_params = (param::NullTerminated::new(ssid), channel);
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::SetApNetCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::SetApNetwork)
+        }
+    }
+
+    pub fn set_ap_passphrase(
+        &mut self,
+        ssid: &[u8],
+        passphrase: &[u8],
+        channel: u8,
+    ) -> Result<(), error::Error<T::Error>> {
+        let send_params = (
+            param::NullTerminated::new(ssid),
+            param::NullTerminated::new(passphrase),
+            channel,
+        );
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::SetApPassphraseCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::SetApPassphrase)
+        }
+    }
+
     pub fn set_key(
         &mut self,
         ssid: &str,
@@ -388,6 +495,63 @@ where
         }
     }
 
+    pub fn set_debug(&mut self, enabled: bool) -> Result<(), error::Error<T::Error>> {
+        let send_params = (u8::from(enabled),);
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::SetDebugCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::SetDebug)
+        }
+    }
+
+    pub fn set_client_cert(&mut self, cert: &[u8]) -> Result<(), error::Error<T::Error>> {
+        let send_params = (cert,);
+        let mut recv_params = (0u8,);
+
+        self.handle_long_send_cmd(
+            command::Command::SetClientCertCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::SetClientCert)
+        }
+    }
+
+    pub fn set_cert_key(&mut self, key: &[u8]) -> Result<(), error::Error<T::Error>> {
+        let send_params = (key,);
+        let mut recv_params = (0u8,);
+
+        self.handle_long_send_cmd(
+            command::Command::SetCertKeyCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::SetCertKey)
+        }
+    }
+
     pub fn disconnect(&mut self) -> Result<(), error::Error<T::Error>> {
         let send_params = (0u8,);
         let mut recv_params = (0u8,);
@@ -476,6 +640,95 @@ where
         Ok(encryption_type)
     }
 
+    pub fn start_server(
+        &mut self,
+        port: u16,
+        socket: types::Socket,
+        protocol_mode: types::ProtocolMode,
+    ) -> Result<(), error::Error<T::Error>> {
+        let send_params = (param::Scalar::be(port), socket.0, u8::from(protocol_mode));
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::StartServerTcpCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::StartServer)
+        }
+    }
+
+    pub fn start_multicast(
+        &mut self,
+        group: no_std_net::Ipv4Addr,
+        port: u16,
+        socket: types::Socket,
+    ) -> Result<(), error::Error<T::Error>> {
+        let send_params = (
+            param::Scalar::be(u32::from(group)),
+            param::Scalar::be(port),
+            socket.0,
+            u8::from(types::ProtocolMode::UdpMulticast),
+        );
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::StartServerTcpCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::StartServer)
+        }
+    }
+
+    pub fn get_server_state(
+        &mut self,
+        socket: types::Socket,
+    ) -> Result<types::TcpState, error::Error<T::Error>> {
+        use core::convert::TryFrom;
+
+        let send_params = (socket.0,);
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::GetStateTcpCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (state,) = recv_params;
+        let state = types::TcpState::try_from(state).map_err(error::Error::BadTcpState)?;
+
+        Ok(state)
+    }
+
+    /// Polls a listening socket; on servers, `AvailDataTcpCmd` returns the socket of a connected
+    /// client instead of a byte count.
+    pub fn avail_server(
+        &mut self,
+        socket: types::Socket,
+    ) -> Result<Option<types::Socket>, error::Error<T::Error>> {
+        let client = self.avail_data(socket)?;
+
+        if client == u16::from(NO_SOCKET) {
+            Ok(None)
+        } else {
+            Ok(Some(types::Socket(client as u8)))
+        }
+    }
+
     pub fn start_client_by_ip(
         &mut self,
         ip: no_std_net::Ipv4Addr,
@@ -501,11 +754,48 @@ where
 
         if status == 1 {
             Ok(())
+        } else if protocol_mode == types::ProtocolMode::Tls {
+            Err(error::Error::TlsHandshake)
         } else {
             Err(error::Error::StartClientByIp)
         }
     }
 
+    /// Connects by hostname, which lets the firmware send SNI and verify the certificate's name.
+    pub fn start_client_by_host(
+        &mut self,
+        hostname: &str,
+        port: u16,
+        socket: types::Socket,
+        protocol_mode: types::ProtocolMode,
+    ) -> Result<(), error::Error<T::Error>> {
+        let send_params = (
+            hostname.as_bytes(),
+            // The firmware ignores the IP when a hostname is given
+            param::Scalar::be(0u32),
+            param::Scalar::be(port),
+            socket.0,
+            u8::from(protocol_mode),
+        );
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::StartClientTcpCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else if protocol_mode == types::ProtocolMode::Tls {
+            Err(error::Error::TlsHandshake)
+        } else {
+            Err(error::Error::StartClientByHost)
+        }
+    }
+
     pub fn stop_client(&mut self, socket: types::Socket) -> Result<(), error::Error<T::Error>> {
         let send_params = (socket.0,);
         let mut recv_params = (0u8,);
@@ -601,6 +891,48 @@ where
         Ok(len.into_inner() as usize)
     }
 
+    pub fn insert_data_buf(
+        &mut self,
+        socket: types::Socket,
+        data: &[u8],
+    ) -> Result<(), error::Error<T::Error>> {
+        let send_params = (socket.0, data);
+        let mut recv_params = (0u8,);
+
+        self.handle_long_send_cmd(
+            command::Command::InsertDatabufCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::InsertDataBuf)
+        }
+    }
+
+    pub fn send_udp_data(&mut self, socket: types::Socket) -> Result<(), error::Error<T::Error>> {
+        let send_params = (socket.0,);
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::SendDataUdpCmd,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (status,) = recv_params;
+
+        if status == 1 {
+            Ok(())
+        } else {
+            Err(error::Error::SendUdpData)
+        }
+    }
+
     pub fn check_data_sent(&mut self, socket: types::Socket) -> Result<(), error::Error<T::Error>> {
         let send_params = (socket.0,);
         let mut recv_params = (0u8,);
@@ -626,9 +958,12 @@ where
         self.handle_cmd(command::Command::GetSocketCmd, &(), &mut recv_params)?;
 
         let (socket,) = recv_params;
-        let socket = types::Socket(socket);
 
-        Ok(socket)
+        if socket == NO_SOCKET {
+            Err(error::Error::NoSocketAvailable)
+        } else {
+            Ok(types::Socket(socket))
+        }
     }
 
     pub fn pin_mode(
@@ -688,6 +1023,40 @@ where
         }
     }
 
+    pub fn digital_read(&mut self, pin: u8) -> Result<u8, error::Error<T::Error>> {
+        let send_params = (pin,);
+        let mut recv_params = (0u8,);
+
+        self.handle_cmd(
+            command::Command::SetDigitalRead,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (value,) = recv_params;
+
+        Ok(value)
+    }
+
+    pub fn analog_read(
+        &mut self,
+        pin: u8,
+        attenuation: types::AdcAttenuation,
+    ) -> Result<u16, error::Error<T::Error>> {
+        let send_params = (pin, u8::from(attenuation));
+        let mut recv_params = (param::Scalar::le(0u32),);
+
+        self.handle_cmd(
+            command::Command::SetAnalogRead,
+            &send_params,
+            &mut recv_params,
+        )?;
+
+        let (value,) = recv_params;
+
+        Ok(value.into_inner() as u16)
+    }
+
     fn handle_cmd<SP, RP>(
         &mut self,
         command: command::Command,
@@ -733,3 +1102,55 @@ where
             .map_err(error::Error::Transport)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTransport;
+
+    #[test]
+    fn temperature_is_little_endian() {
+        let mut transport = FakeTransport::new();
+        transport.expect(
+            command::Command::GetTemperatureCmd,
+            &[&[0x00, 0x00, 0x20, 0x42]],
+        );
+        let mut handler = Handler::new(transport);
+
+        assert_eq!(40.0, handler.get_temperature().unwrap().celsius());
+    }
+
+    #[test]
+    fn ping_sends_big_endian_address() {
+        let mut transport = FakeTransport::new();
+        transport.expect(command::Command::PingCmd, &[&[20, 0]]);
+        let mut handler = Handler::new(transport);
+
+        let ip = no_std_net::Ipv4Addr::new(192, 168, 1, 2);
+        assert_eq!(
+            time::Duration::from_millis(20),
+            handler.ping(ip, 64).unwrap()
+        );
+        let (_, params) = &handler.transport().sent[0];
+        assert_eq!(&[2, 4, 192, 168, 1, 2, 1, 64][..], &params[..]);
+    }
+
+    #[test]
+    fn ping_maps_negative_replies_to_failures() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(command::Command::PingCmd, &[&(-2i16).to_le_bytes()])
+            .expect(command::Command::PingCmd, &[&(-100i16).to_le_bytes()]);
+        let mut handler = Handler::new(transport);
+
+        let ip = no_std_net::Ipv4Addr::new(192, 168, 1, 2);
+        assert!(matches!(
+            handler.ping(ip, 64),
+            Err(error::Error::Ping(types::PingFailure::Timeout))
+        ));
+        assert!(matches!(
+            handler.ping(ip, 64),
+            Err(error::Error::Ping(types::PingFailure::Error))
+        ));
+    }
+}
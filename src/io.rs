@@ -0,0 +1,301 @@
+//! `embedded-io`, `embedded-io-async` and `std::io` support for `SharedClient`.
+//!
+//! Reads block until data arrives and return 0 once the peer has closed the connection
+//! (`TcpState::CloseWait` or `TcpState::Closed`) and everything has been read.
+
+use crate::error;
+use crate::shared::SharedClient;
+use crate::transport;
+use crate::Wifi;
+use core::cell;
+use core::fmt;
+use core::time;
+
+/// How long blocking reads sleep between polls of the firmware.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+impl<E> embedded_io::Error for error::Error<E>
+where
+    E: fmt::Debug,
+{
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind;
+
+        match self {
+            error::Error::ConnectRefused(_) => ErrorKind::ConnectionRefused,
+            error::Error::ConnectTimeout(_) | error::Error::TcpStateTimeout(_) => {
+                ErrorKind::TimedOut
+            }
+            error::Error::NotConnected => ErrorKind::NotConnected,
+            error::Error::ClientInvalidated => ErrorKind::ConnectionAborted,
+            error::Error::DataTooLong | error::Error::NoBuffer | error::Error::WrongWifi => {
+                ErrorKind::InvalidInput
+            }
+            error::Error::Unsupported(_) => ErrorKind::Unsupported,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl<'a, T, const N: usize> embedded_io::ErrorType for SharedClient<'a, T, N>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    type Error = error::Error<T::Error>;
+}
+
+impl<'a, T, const N: usize> embedded_io::Read for SharedClient<'a, T, N>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let client = &mut self.client;
+        block(self.wifi, |wifi| client.try_recv(wifi, buf))
+    }
+}
+
+/// Reads through the client's own buffer, so this needs a client with `N > 0`; with `N == 0`
+/// `fill_buf` fails with `Error::NoBuffer`.
+impl<'a, T, const N: usize> embedded_io::BufRead for SharedClient<'a, T, N>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+        let client = &mut self.client;
+        block(self.wifi, |wifi| client.try_fill_buffer(wifi))?;
+        Ok(self.client.buffered())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.client.consume(amt)
+    }
+}
+
+impl<'a, T, const N: usize> embedded_io::Write for SharedClient<'a, T, N>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.send(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        SharedClient::flush(self)
+    }
+}
+
+fn block<T, R>(
+    wifi: &cell::RefCell<Wifi<T>>,
+    mut f: impl FnMut(&mut Wifi<T>) -> nb::Result<R, error::Error<T::Error>>,
+) -> Result<R, error::Error<T::Error>>
+where
+    T: transport::Transport,
+{
+    loop {
+        let mut wifi = wifi.borrow_mut();
+        match f(&mut wifi) {
+            Ok(result) => return Ok(result),
+            Err(nb::Error::Other(err)) => return Err(err),
+            Err(nb::Error::WouldBlock) => wifi.handler.delay(POLL_INTERVAL)?,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+mod asynch {
+    use crate::shared::SharedClient;
+    use crate::transport;
+    use core::fmt;
+    use core::future;
+    use core::pin;
+    use core::task;
+
+    // The firmware can't signal incoming data, so waiting means yielding to the executor and
+    // polling again.
+
+    impl<'a, T, const N: usize> embedded_io_async::Read for SharedClient<'a, T, N>
+    where
+        T: transport::Transport,
+        T::Error: fmt::Debug,
+    {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                // Bound first so the `Wifi` isn't borrowed across the yield
+                let result = self.try_recv(buf);
+                match result {
+                    Ok(len) => return Ok(len),
+                    Err(nb::Error::Other(err)) => return Err(err),
+                    Err(nb::Error::WouldBlock) => YieldNow(false).await,
+                }
+            }
+        }
+    }
+
+    impl<'a, T, const N: usize> embedded_io_async::BufRead for SharedClient<'a, T, N>
+    where
+        T: transport::Transport,
+        T::Error: fmt::Debug,
+    {
+        async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+            loop {
+                let result = self.client.try_fill_buffer(&mut self.wifi.borrow_mut());
+                match result {
+                    Ok(()) => break,
+                    Err(nb::Error::Other(err)) => return Err(err),
+                    Err(nb::Error::WouldBlock) => YieldNow(false).await,
+                }
+            }
+            Ok(self.client.buffered())
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.client.consume(amt)
+        }
+    }
+
+    impl<'a, T, const N: usize> embedded_io_async::Write for SharedClient<'a, T, N>
+    where
+        T: transport::Transport,
+        T::Error: fmt::Debug,
+    {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            embedded_io::Write::write(self, buf)
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            SharedClient::flush(self)
+        }
+    }
+
+    struct YieldNow(bool);
+
+    impl future::Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: pin::Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<()> {
+            if self.0 {
+                task::Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                task::Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod std_io {
+    use crate::error;
+    use crate::shared::SharedClient;
+    use crate::transport;
+    use core::fmt;
+
+    impl<'a, T, const N: usize> std::io::Read for SharedClient<'a, T, N>
+    where
+        T: transport::Transport,
+        T::Error: fmt::Debug,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            embedded_io::Read::read(self, buf).map_err(to_std)
+        }
+    }
+
+    impl<'a, T, const N: usize> std::io::BufRead for SharedClient<'a, T, N>
+    where
+        T: transport::Transport,
+        T::Error: fmt::Debug,
+    {
+        fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+            embedded_io::BufRead::fill_buf(self).map_err(to_std)
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.client.consume(amt)
+        }
+    }
+
+    impl<'a, T, const N: usize> std::io::Write for SharedClient<'a, T, N>
+    where
+        T: transport::Transport,
+        T::Error: fmt::Debug,
+    {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            embedded_io::Write::write(self, buf).map_err(to_std)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            SharedClient::flush(self).map_err(to_std)
+        }
+    }
+
+    fn to_std<E>(err: error::Error<E>) -> std::io::Error
+    where
+        E: fmt::Debug,
+    {
+        let kind = embedded_io::Error::kind(&err).into();
+        std::io::Error::new(kind, std::format!("{:?}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::Command;
+    use crate::error;
+    use crate::shared::SharedClient;
+    use crate::testing::FakeTransport;
+    use crate::types;
+    use crate::Wifi;
+    use core::cell;
+    use embedded_io::{BufRead, Write};
+
+    fn connected(transport: &mut FakeTransport) -> &mut FakeTransport {
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+    }
+
+    #[test]
+    fn fill_buf_needs_a_buffer() {
+        let mut transport = FakeTransport::new();
+        connected(&mut transport);
+        let wifi = cell::RefCell::new(Wifi::new(transport));
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let client = wifi.borrow_mut().new_client_with_buffer::<0>().unwrap();
+        let mut client = SharedClient::new(&wifi, client);
+        client
+            .connect_ipv4(ip, 80, types::ProtocolMode::Tcp)
+            .unwrap();
+        assert!(matches!(client.fill_buf(), Err(error::Error::NoBuffer)));
+        assert!(wifi.borrow().handler.transport().is_done());
+    }
+
+    #[test]
+    fn empty_write_sends_nothing() {
+        let mut transport = FakeTransport::new();
+        connected(&mut transport);
+        let wifi = cell::RefCell::new(Wifi::new(transport));
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let mut client = SharedClient::create(&wifi).unwrap();
+        client
+            .connect_ipv4(ip, 80, types::ProtocolMode::Tcp)
+            .unwrap();
+        assert_eq!(0, client.write(&[]).unwrap());
+        assert!(wifi.borrow().handler.transport().is_done());
+    }
+}
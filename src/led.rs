@@ -0,0 +1,227 @@
+//! An RGB status LED attached to the ESP32, with blink/breathe patterns and an automatic mode
+//! that mirrors the connection state.
+
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::Wifi;
+use core::time;
+
+const STATE_POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+/// The ESP32 pins wired to the RGB LED.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LedPins {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    /// Common-anode LEDs light up when their pin is driven low, so all duty cycles are inverted.
+    pub common_anode: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pattern {
+    Off,
+    Solid(Color),
+    Blink {
+        color: Color,
+        period: time::Duration,
+    },
+    Breathe {
+        color: Color,
+        period: time::Duration,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusLed {
+    pattern: Pattern,
+    auto: bool,
+    last_state_poll: Option<time::Duration>,
+    last_color: Option<Color>,
+}
+
+impl LedPins {
+    /// The LED on Arduino boards with a NINA-W102 module, like the MKR WiFi 1010.
+    pub const ARDUINO: Self = Self {
+        red: 26,
+        green: 25,
+        blue: 27,
+        common_anode: false,
+    };
+
+    /// The LED on Adafruit AirLift boards.
+    pub const AIRLIFT: Self = Self {
+        red: 26,
+        green: 25,
+        blue: 27,
+        common_anode: true,
+    };
+}
+
+impl Default for LedPins {
+    /// The pins `Wifi::set_led` has always used.
+    fn default() -> Self {
+        Self {
+            red: 25,
+            green: 26,
+            blue: 27,
+            common_anode: false,
+        }
+    }
+}
+
+impl Color {
+    pub const OFF: Self = Self::new(0, 0, 0);
+    pub const RED: Self = Self::new(255, 0, 0);
+    pub const GREEN: Self = Self::new(0, 255, 0);
+    pub const BLUE: Self = Self::new(0, 0, 255);
+    pub const YELLOW: Self = Self::new(255, 160, 0);
+    pub const CYAN: Self = Self::new(0, 255, 255);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    fn scale(self, level: u8) -> Self {
+        let scale = |c: u8| (u16::from(c) * u16::from(level) / 255) as u8;
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+impl Pattern {
+    /// The pattern the automatic mode shows for a connection state.
+    pub fn for_connection_state(state: types::ConnectionState) -> Self {
+        use types::ConnectionState::*;
+
+        match state {
+            IdleStatus => Pattern::Blink {
+                color: Color::YELLOW,
+                period: time::Duration::from_millis(500),
+            },
+            ScanCompleted => Pattern::Breathe {
+                color: Color::BLUE,
+                period: time::Duration::from_secs(2),
+            },
+            Connected | ApConnected => Pattern::Solid(Color::GREEN),
+            ApListening => Pattern::Breathe {
+                color: Color::CYAN,
+                period: time::Duration::from_secs(2),
+            },
+            NoSsidAvail | ConnectFailed | ConnectionLost | ApFailed => Pattern::Blink {
+                color: Color::RED,
+                period: time::Duration::from_secs(1),
+            },
+            Disconnected => Pattern::Off,
+        }
+    }
+
+    /// The color this pattern shows at a point in time.
+    pub fn color_at(self, now: time::Duration) -> Color {
+        match self {
+            Pattern::Off => Color::OFF,
+            Pattern::Solid(color) => color,
+            Pattern::Blink { color, period } => {
+                if phase(now, period) < 128 {
+                    color
+                } else {
+                    Color::OFF
+                }
+            }
+            Pattern::Breathe { color, period } => {
+                let phase = phase(now, period);
+                let level = if phase < 128 {
+                    phase * 2
+                } else {
+                    (255 - phase) * 2
+                };
+                color.scale(level)
+            }
+        }
+    }
+}
+
+impl StatusLed {
+    pub fn new(pattern: Pattern) -> Self {
+        let auto = false;
+        let last_state_poll = None;
+        let last_color = None;
+        Self {
+            pattern,
+            auto,
+            last_state_poll,
+            last_color,
+        }
+    }
+
+    /// Creates a LED in automatic mode, showing the connection state.
+    pub fn auto() -> Self {
+        let mut this = Self::new(Pattern::Off);
+        this.auto = true;
+        this
+    }
+
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Shows a fixed pattern, leaving automatic mode.
+    pub fn set_pattern(&mut self, pattern: Pattern) {
+        self.pattern = pattern;
+        self.auto = false;
+    }
+
+    pub fn set_auto(&mut self, auto: bool) {
+        self.auto = auto;
+        self.last_state_poll = None;
+    }
+
+    /// Updates the LED; call this regularly from the main loop.
+    ///
+    /// `now` is any monotonic timestamp. The LED is only written when its color changes, and in
+    /// automatic mode the connection state is polled at most twice a second.
+    pub fn tick<T>(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        now: time::Duration,
+    ) -> Result<(), error::Error<T::Error>>
+    where
+        T: transport::Transport,
+    {
+        if self.auto {
+            let due = match self.last_state_poll {
+                None => true,
+                Some(last) => now < last || now - last >= STATE_POLL_INTERVAL,
+            };
+            if due {
+                self.pattern = Pattern::for_connection_state(wifi.connection_state()?);
+                self.last_state_poll = Some(now);
+            }
+        }
+
+        let color = self.pattern.color_at(now);
+        if self.last_color != Some(color) {
+            wifi.set_led(color.r, color.g, color.b)?;
+            self.last_color = Some(color);
+        }
+
+        Ok(())
+    }
+}
+
+/// Where `now` falls within `period`, scaled to 0..=255.
+fn phase(now: time::Duration, period: time::Duration) -> u8 {
+    let period = period.as_millis();
+    if period == 0 {
+        return 0;
+    }
+    ((now.as_millis() % period) * 256 / period) as u8
+}
@@ -1,44 +1,145 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 use core::marker;
+use core::sync::atomic;
 use core::time;
 
+pub mod board;
 mod command;
 mod encoding;
 mod error;
 mod full_duplex;
+pub mod gpio;
 mod handler;
+#[cfg(feature = "embedded-io")]
+mod io;
+pub mod led;
+pub mod manager;
+#[cfg(feature = "embedded-nal")]
+pub mod nal;
 mod param;
 mod params;
+mod pem;
+pub mod shared;
+#[cfg(feature = "embedded-svc")]
+pub mod svc;
+#[cfg(test)]
+mod testing;
 pub mod transport;
 pub mod types;
+mod udp;
 
 pub use error::Error;
+pub use udp::UdpSocket;
 
-const BUFFER_CAPACITY: usize = 4096;
+/// The receive buffer size of clients created without an explicit one.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 4096;
+/// The most payload the firmware accepts or returns in one SPI command.
+const MAX_TRANSFER_LEN: usize = 4096;
+/// How many sockets `Wifi` keeps track of; the firmware itself has fewer.
+const MAX_SOCKETS: usize = 16;
+
+static NEXT_WIFI_ID: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
 
 #[derive(Debug)]
 pub struct Wifi<T> {
     handler: handler::Handler<T>,
+    board: Option<&'static board::BoardProfile>,
+    led_pins: led::LedPins,
     led_init: bool,
+    firmware_debug: Option<bool>,
+    firmware_version: Option<Option<types::FirmwareVersion>>,
+    firmware_vendor: Option<types::FirmwareVendor>,
+    network: Option<Network>,
+    sockets: arrayvec::ArrayVec<[OpenSocket; MAX_SOCKETS]>,
+    id: usize,
+    generation: u32,
 }
 
+/// A TCP connection with an `N` byte receive buffer.
+///
+/// Reads smaller than what the firmware has pending go through the buffer; larger ones are copied
+/// straight into the caller's slice, so `N` can be small or even 0 if reads are sized accordingly.
 #[derive(Debug)]
-pub struct Client<T> {
+pub struct Client<T, const N: usize = DEFAULT_BUFFER_CAPACITY> {
     socket: types::Socket,
+    wifi_id: usize,
+    generation: u32,
+    started: bool,
+    closed: bool,
     buffer_offset: usize,
-    buffer: arrayvec::ArrayVec<[u8; BUFFER_CAPACITY]>,
+    buffer_len: usize,
+    buffer: [u8; N],
+    phantom: marker::PhantomData<T>,
+}
+
+/// A listening TCP socket.
+#[derive(Debug)]
+pub struct Server<T> {
+    socket: types::Socket,
+    port: u16,
+    wifi_id: usize,
+    generation: u32,
     phantom: marker::PhantomData<T>,
 }
 
+/// A socket handed out by `Wifi::allocate_socket` or `Server::accept`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct OpenSocket {
+    socket: types::Socket,
+    kind: types::SocketKind,
+    /// Whether the socket is in use on the firmware side; until then the firmware keeps offering
+    /// it to `GetSocketCmd` and incoming connections.
+    started: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Network {
+    ssid: arrayvec::ArrayVec<[u8; 32]>,
+    password: Option<arrayvec::ArrayVec<[u8; 64]>>,
+}
+
 impl<T> Wifi<T>
 where
     T: transport::Transport,
 {
     pub fn new(transport: T) -> Self {
         let handler = handler::Handler::new(transport);
+        let board = None;
+        let led_pins = led::LedPins::default();
         let led_init = false;
-        Self { handler, led_init }
+        let firmware_debug = None;
+        let firmware_version = None;
+        let firmware_vendor = None;
+        let network = None;
+        let sockets = arrayvec::ArrayVec::new();
+        let id = next_wifi_id();
+        let generation = 0;
+        Self {
+            handler,
+            board,
+            led_pins,
+            led_init,
+            firmware_debug,
+            firmware_version,
+            firmware_vendor,
+            network,
+            sockets,
+            id,
+            generation,
+        }
+    }
+
+    /// Hard-resets the module.
+    ///
+    /// The module forgets its network configuration and sockets, so all clients are invalidated.
+    pub fn reset(&mut self) -> Result<(), error::Error<T::Error>> {
+        self.handler.reset()?;
+        self.led_init = false;
+        self.firmware_debug = None;
+        self.sockets.clear();
+        self.generation = self.generation.wrapping_add(1);
+        Ok(())
     }
 
     pub fn get_firmware_version(
@@ -47,35 +148,319 @@ where
         self.handler.get_firmware_version()
     }
 
+    /// Returns the parsed firmware version, fetching it from the module on first use.
+    pub fn firmware_version(&mut self) -> Result<types::FirmwareVersion, error::Error<T::Error>> {
+        self.cached_firmware_version()?
+            .ok_or(error::Error::BadFirmwareVersion)
+    }
+
+    /// Whether the attached firmware is new enough to support `capability`.
+    ///
+    /// Unless the firmware fork is known (see `set_firmware_vendor`), only capabilities that both
+    /// forks have are reported. Firmware with an unparseable version string is assumed to support
+    /// nothing.
+    pub fn supports(
+        &mut self,
+        capability: types::Capability,
+    ) -> Result<bool, error::Error<T::Error>> {
+        match self.cached_firmware_version()? {
+            Some(version) => Ok(version.supports(capability, self.firmware_vendor)),
+            None => Ok(false),
+        }
+    }
+
+    /// Tells `supports` which firmware fork the module runs; `apply_board` sets this too.
+    pub fn set_firmware_vendor(&mut self, vendor: types::FirmwareVendor) {
+        self.firmware_vendor = Some(vendor);
+    }
+
+    fn cached_firmware_version(
+        &mut self,
+    ) -> Result<Option<types::FirmwareVersion>, error::Error<T::Error>> {
+        if let Some(version) = self.firmware_version {
+            return Ok(version);
+        }
+
+        let raw = self.handler.get_firmware_version()?;
+        let version = types::FirmwareVersion::parse(&raw);
+        if version.is_none() {
+            log::warn!("unrecognized firmware version {:?}", raw);
+        }
+        self.firmware_version = Some(version);
+        Ok(version)
+    }
+
+    fn require(&mut self, capability: types::Capability) -> Result<(), error::Error<T::Error>> {
+        if self.supports(capability)? {
+            Ok(())
+        } else {
+            Err(error::Error::Unsupported(capability))
+        }
+    }
+
+    /// Provisions the client certificate and private key presented by later
+    /// `ProtocolMode::Tls` connections, for servers that require mutual TLS.
+    ///
+    /// Both must be PEM encoded; they are checked for PEM structure before anything is sent.
+    pub fn set_client_certificate(
+        &mut self,
+        cert_pem: &[u8],
+        key_pem: &[u8],
+    ) -> Result<(), error::Error<T::Error>> {
+        // The sizes of the firmware's certificate and key buffers
+        const MAX_CERT_LEN: usize = 1300;
+        const MAX_KEY_LEN: usize = 1700;
+
+        if !pem::is_pem(cert_pem, &["CERTIFICATE"]) {
+            return Err(error::Error::InvalidPem);
+        }
+        if !pem::is_pem(
+            key_pem,
+            &["PRIVATE KEY", "RSA PRIVATE KEY", "EC PRIVATE KEY"],
+        ) {
+            return Err(error::Error::InvalidPem);
+        }
+        if cert_pem.len() > MAX_CERT_LEN || key_pem.len() > MAX_KEY_LEN {
+            return Err(error::Error::DataTooLong);
+        }
+
+        self.require(types::Capability::ClientCertificate)?;
+        self.handler.set_client_cert(cert_pem)?;
+        self.handler.set_cert_key(key_pem)
+    }
+
+    pub fn mac_address(&mut self) -> Result<[u8; 6], error::Error<T::Error>> {
+        self.handler.get_mac_address()
+    }
+
+    /// Makes the ESP32 print its own debug log on its UART.
+    ///
+    /// Firmware without `SetDebugCmd` answers it with an error response instead of a status, so
+    /// this checks `Capability::FirmwareDebug` first and fails with `Error::Unsupported` there.
+    pub fn set_firmware_debug(&mut self, enabled: bool) -> Result<(), error::Error<T::Error>> {
+        self.require(types::Capability::FirmwareDebug)?;
+        self.handler.set_debug(enabled)?;
+        self.firmware_debug = Some(enabled);
+        Ok(())
+    }
+
+    pub fn diagnostics(&mut self) -> Result<types::Diagnostics, error::Error<T::Error>> {
+        let firmware_version = self.handler.get_firmware_version()?;
+        let mac_address = self.handler.get_mac_address()?;
+        let connection_state = self.handler.get_connection_state()?;
+        let temperature = if self.supports(types::Capability::Temperature)? {
+            Some(self.handler.get_temperature()?)
+        } else {
+            None
+        };
+        let firmware_debug = self.firmware_debug;
+
+        Ok(types::Diagnostics {
+            firmware_version,
+            mac_address,
+            connection_state,
+            temperature,
+            firmware_debug,
+        })
+    }
+
+    /// Reads the ESP32 die temperature.
+    pub fn temperature(&mut self) -> Result<types::Temperature, error::Error<T::Error>> {
+        self.require(types::Capability::Temperature)?;
+        self.handler.get_temperature()
+    }
+
+    /// Applies a board's LED wiring and restricts the GPIO API to the pins the board breaks out.
+    pub fn apply_board(&mut self, board: &'static board::BoardProfile) {
+        if let Some(led_pins) = board.led {
+            self.set_led_pins(led_pins);
+        }
+        self.set_firmware_vendor(board.firmware_vendor);
+        self.board = Some(board);
+    }
+
+    /// Changes which ESP32 pins `set_led` drives.
+    pub fn set_led_pins(&mut self, led_pins: led::LedPins) {
+        self.led_pins = led_pins;
+        self.led_init = false;
+    }
+
     pub fn set_led(&mut self, r: u8, g: u8, b: u8) -> Result<(), error::Error<T::Error>> {
+        let pins = self.led_pins;
+
         if !self.led_init {
-            self.handler.pin_mode(25, types::PinMode::Output)?;
-            self.handler.pin_mode(26, types::PinMode::Output)?;
-            self.handler.pin_mode(27, types::PinMode::Output)?;
+            self.handler.pin_mode(pins.red, types::PinMode::Output)?;
+            self.handler.pin_mode(pins.green, types::PinMode::Output)?;
+            self.handler.pin_mode(pins.blue, types::PinMode::Output)?;
             self.led_init = true;
         }
 
-        self.handler.analog_write(25, r)?;
-        self.handler.analog_write(26, g)?;
-        self.handler.analog_write(27, b)?;
+        let (r, g, b) = if pins.common_anode {
+            (!r, !g, !b)
+        } else {
+            (r, g, b)
+        };
+
+        self.handler.analog_write(pins.red, r)?;
+        self.handler.analog_write(pins.green, g)?;
+        self.handler.analog_write(pins.blue, b)?;
 
         Ok(())
     }
 
+    pub fn pin_mode(
+        &mut self,
+        pin: u8,
+        mode: types::PinMode,
+    ) -> Result<(), error::Error<T::Error>> {
+        match mode {
+            types::PinMode::Output => gpio::check_output(pin)?,
+            types::PinMode::Input | types::PinMode::InputPullup => gpio::check_input(pin)?,
+        }
+        self.check_board_pin(pin)?;
+        self.handler.pin_mode(pin, mode)
+    }
+
+    pub fn digital_write(&mut self, pin: u8, high: bool) -> Result<(), error::Error<T::Error>> {
+        gpio::check_output(pin)?;
+        self.check_board_pin(pin)?;
+        self.handler.digital_write(pin, u8::from(high))
+    }
+
+    pub fn digital_read(&mut self, pin: u8) -> Result<bool, error::Error<T::Error>> {
+        gpio::check_input(pin)?;
+        self.check_board_pin(pin)?;
+        self.require(types::Capability::DigitalRead)?;
+        Ok(self.handler.digital_read(pin)? != 0)
+    }
+
+    /// Sets the PWM duty cycle of a pin, where 255 is fully on.
+    pub fn analog_write(&mut self, pin: u8, duty: u8) -> Result<(), error::Error<T::Error>> {
+        gpio::check_output(pin)?;
+        self.check_board_pin(pin)?;
+        self.handler.analog_write(pin, duty)
+    }
+
+    /// Samples a pin with the 12-bit ADC1; only ADC1 pins are usable while WiFi is on.
+    pub fn analog_read(
+        &mut self,
+        pin: u8,
+        attenuation: types::AdcAttenuation,
+    ) -> Result<u16, error::Error<T::Error>> {
+        gpio::check_analog_input(pin)?;
+        self.check_board_pin(pin)?;
+        self.require(types::Capability::AnalogRead)?;
+        self.handler.analog_read(pin, attenuation)
+    }
+
+    fn check_board_pin(&self, pin: u8) -> Result<(), error::Error<T::Error>> {
+        match self.board {
+            Some(board) if !board.has_gpio_pin(pin) && !self.is_led_pin(pin) => {
+                Err(error::Error::InvalidPin(pin))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_led_pin(&self, pin: u8) -> bool {
+        match self.board.and_then(|board| board.led) {
+            Some(led) => pin == led.red || pin == led.green || pin == led.blue,
+            None => false,
+        }
+    }
+
+    /// Joins a network as a station, or starts an access point.
+    ///
+    /// With a `connect_timeout`, this waits until the station is connected or the access point
+    /// is listening.
     pub fn configure(
         &mut self,
         config: types::Config,
         connect_timeout: Option<time::Duration>,
     ) -> Result<(), error::Error<T::Error>> {
-        match config {
+        let network = match config {
             types::Config::Station(station_config) => match station_config.network {
-                types::NetworkConfig::Open { ssid } => self.handler.set_network(ssid)?,
-                types::NetworkConfig::Password { ssid, password } => {
-                    self.handler.set_passphrase(ssid, password)?
-                }
+                types::NetworkConfig::Open { ssid } => Network {
+                    ssid: copy_bytes(ssid)?,
+                    password: None,
+                },
+                types::NetworkConfig::Password { ssid, password } => Network {
+                    ssid: copy_bytes(ssid)?,
+                    password: Some(copy_bytes(password)?),
+                },
             },
-            types::Config::AccessPoint(_) => unimplemented!(),
+            types::Config::AccessPoint(ap_config) => {
+                return self.start_access_point(ap_config, connect_timeout)
+            }
+        };
+
+        self.join(network, connect_timeout)
+    }
+
+    /// Rejoins the network most recently passed to `configure`.
+    pub fn reconnect(
+        &mut self,
+        connect_timeout: Option<time::Duration>,
+    ) -> Result<(), error::Error<T::Error>> {
+        let network = self.network.clone().ok_or(error::Error::NotConfigured)?;
+        self.join(network, connect_timeout)
+    }
+
+    /// Leaves the current network and waits until the module reports that it is disconnected.
+    ///
+    /// All open sockets are closed and the clients using them invalidated.
+    pub fn disconnect(&mut self, timeout: time::Duration) -> Result<(), error::Error<T::Error>> {
+        // Every handle is invalidated even if the module stops answering halfway through, since
+        // their sockets can't be trusted either way
+        let mut result = Ok(());
+        for open in self.sockets.drain(..) {
+            match self.handler.stop_client(open.socket) {
+                Ok(()) | Err(error::Error::StopClient) => {}
+                Err(err) => result = result.and(Err(err)),
+            }
         }
+        self.generation = self.generation.wrapping_add(1);
+        result?;
+
+        self.handler.disconnect()?;
+        self.await_connection_state_matching(
+            |state| {
+                state == types::ConnectionState::Disconnected
+                    || state == types::ConnectionState::IdleStatus
+            },
+            timeout,
+        )
+    }
+
+    pub fn connection_state(&mut self) -> Result<types::ConnectionState, error::Error<T::Error>> {
+        self.handler.get_connection_state()
+    }
+
+    /// Returns why the module last left or failed to join a network.
+    pub fn disconnect_reason(&mut self) -> Result<types::DisconnectReason, error::Error<T::Error>> {
+        self.require(types::Capability::DisconnectReason)?;
+        let reason = self.handler.get_reason_code()?;
+        Ok(types::DisconnectReason::from(reason))
+    }
+
+    pub fn await_connection_state(
+        &mut self,
+        connection_state: types::ConnectionState,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.await_connection_state_matching(|state| state == connection_state, timeout)
+    }
+
+    fn join(
+        &mut self,
+        network: Network,
+        connect_timeout: Option<time::Duration>,
+    ) -> Result<(), error::Error<T::Error>> {
+        match network.password {
+            None => self.handler.set_network(&network.ssid)?,
+            Some(ref password) => self.handler.set_passphrase(&network.ssid, password)?,
+        }
+        self.network = Some(network);
 
         if let Some(connect_timeout) = connect_timeout {
             self.await_connection_state(types::ConnectionState::Connected, connect_timeout)?;
@@ -84,9 +469,45 @@ where
         Ok(())
     }
 
-    pub fn await_connection_state(
+    fn start_access_point(
         &mut self,
-        connection_state: types::ConnectionState,
+        ap_config: types::AccessPointConfig,
+        connect_timeout: Option<time::Duration>,
+    ) -> Result<(), error::Error<T::Error>> {
+        // The firmware's SSID and passphrase buffers
+        if ap_config.ssid.len() > 32 || ap_config.password.len() > 64 {
+            return Err(error::Error::DataTooLong);
+        }
+
+        if ap_config.password.is_empty() {
+            self.handler
+                .set_ap_network(ap_config.ssid, ap_config.channel)?;
+        } else {
+            self.handler.set_ap_passphrase(
+                ap_config.ssid,
+                ap_config.password,
+                ap_config.channel,
+            )?;
+        }
+        // There is no station network to rejoin anymore
+        self.network = None;
+
+        if let Some(connect_timeout) = connect_timeout {
+            self.await_connection_state_matching(
+                |state| {
+                    state == types::ConnectionState::ApListening
+                        || state == types::ConnectionState::ApConnected
+                },
+                connect_timeout,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn await_connection_state_matching(
+        &mut self,
+        predicate: impl Fn(types::ConnectionState) -> bool,
         timeout: time::Duration,
     ) -> Result<(), error::Error<T::Error>> {
         const POLL_INTEVAL: time::Duration = time::Duration::from_millis(100);
@@ -96,7 +517,7 @@ where
         let mut actual_connection_state;
         loop {
             actual_connection_state = self.handler.get_connection_state()?;
-            if connection_state == actual_connection_state {
+            if predicate(actual_connection_state) {
                 return Ok(());
             }
 
@@ -109,9 +530,42 @@ where
             }
         }
 
-        Err(error::Error::ConnectionFailure(actual_connection_state))
+        let reason = if self.supports(types::Capability::DisconnectReason)? {
+            let reason = self.handler.get_reason_code()?;
+            Some(types::DisconnectReason::from(reason))
+        } else {
+            None
+        };
+
+        Err(error::Error::ConnectionFailure(
+            actual_connection_state,
+            reason,
+        ))
+    }
+
+    fn check_client<const N: usize>(
+        &self,
+        client: &Client<T, N>,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.check_handle(client.wifi_id, client.generation)
     }
 
+    /// Checks that a handle was created by this `Wifi` and hasn't outlived a disconnect.
+    pub(crate) fn check_handle(
+        &self,
+        wifi_id: usize,
+        generation: u32,
+    ) -> Result<(), error::Error<T::Error>> {
+        if wifi_id != self.id {
+            Err(error::Error::WrongWifi)
+        } else if generation == self.generation {
+            Ok(())
+        } else {
+            Err(error::Error::ClientInvalidated)
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
     pub fn scan_networks<'a>(
         &'a mut self,
     ) -> Result<
@@ -165,24 +619,309 @@ where
         self.handler.get_host_by_name()
     }
 
+    /// Sends a single ICMP echo request and returns the round-trip time.
+    ///
+    /// The firmware applies its own reply timeout; a missing reply is reported as
+    /// `Error::Ping(PingFailure::Timeout)`.
+    pub fn ping(
+        &mut self,
+        ip: no_std_net::Ipv4Addr,
+        ttl: u8,
+    ) -> Result<time::Duration, error::Error<T::Error>> {
+        self.require(types::Capability::Ping)?;
+        self.handler.ping(ip, ttl)
+    }
+
+    pub fn ping_host(
+        &mut self,
+        hostname: &str,
+        ttl: u8,
+    ) -> Result<time::Duration, error::Error<T::Error>> {
+        let ip = self.resolve(hostname)?;
+        self.ping(ip, ttl)
+    }
+
+    /// Sends `count` echo requests one after the other and summarizes the replies.
+    ///
+    /// Probes that fail with a `PingFailure` count as lost; transport errors abort the run.
+    pub fn ping_stats(
+        &mut self,
+        ip: no_std_net::Ipv4Addr,
+        ttl: u8,
+        count: u16,
+    ) -> Result<types::PingStats, error::Error<T::Error>> {
+        let mut received = 0;
+        let mut total = time::Duration::new(0, 0);
+        let mut min = time::Duration::new(0, 0);
+        let mut max = time::Duration::new(0, 0);
+
+        for _ in 0..count {
+            match self.ping(ip, ttl) {
+                Ok(rtt) => {
+                    if received == 0 || rtt < min {
+                        min = rtt;
+                    }
+                    if rtt > max {
+                        max = rtt;
+                    }
+                    total += rtt;
+                    received += 1;
+                }
+                Err(error::Error::Ping(failure)) => {
+                    log::debug!("ping to {} failed: {:?}", ip, failure);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let avg = if received == 0 {
+            time::Duration::new(0, 0)
+        } else {
+            total / u32::from(received)
+        };
+
+        Ok(types::PingStats {
+            sent: count,
+            received,
+            min,
+            avg,
+            max,
+        })
+    }
+
+    /// Creates a client on a free socket.
+    ///
+    /// The firmware only reserves the socket once the client connects, so creating another client
+    /// or UDP socket before that fails with `Error::NoSocketAvailable`.
     pub fn new_client(&mut self) -> Result<Client<T>, error::Error<T::Error>> {
-        let socket = self.handler.get_socket()?;
-        let buffer_offset = 0;
-        let buffer = arrayvec::ArrayVec::new();
+        self.new_client_with_buffer()
+    }
+
+    /// Like `new_client`, but with an `N` byte receive buffer instead of the default one.
+    pub fn new_client_with_buffer<const N: usize>(
+        &mut self,
+    ) -> Result<Client<T, N>, error::Error<T::Error>> {
+        let socket = self.allocate_socket(types::SocketKind::TcpClient)?;
+        Ok(Client::new(socket, self.id, self.generation, false))
+    }
+
+    /// Creates a UDP socket on a free socket; like with `new_client`, the firmware only reserves
+    /// it once the socket is bound or sends something.
+    pub fn new_udp_socket(&mut self) -> Result<UdpSocket<T>, error::Error<T::Error>> {
+        let socket = self.allocate_socket(types::SocketKind::Udp)?;
+        Ok(UdpSocket::new(socket, self.id, self.generation))
+    }
+
+    /// Starts listening for TCP connections on `port`.
+    pub fn new_server(&mut self, port: u16) -> Result<Server<T>, error::Error<T::Error>> {
+        let socket = self.allocate_socket(types::SocketKind::TcpServer)?;
+        let mut started = false;
+        if let Err(err) = self.start_socket(socket, &mut started, |handler| {
+            handler.start_server(port, socket, types::ProtocolMode::Tcp)
+        }) {
+            self.release_socket(socket);
+            return Err(err);
+        }
+        let wifi_id = self.id;
+        let generation = self.generation;
         let phantom = marker::PhantomData;
-        Ok(Client {
+        Ok(Server {
             socket,
-            buffer_offset,
-            buffer,
+            port,
+            wifi_id,
+            generation,
             phantom,
         })
     }
+
+    /// Lists the sockets handed out by this driver that haven't been closed yet, to help find
+    /// leaks.
+    pub fn open_sockets(
+        &mut self,
+    ) -> Result<arrayvec::ArrayVec<[types::SocketInfo; MAX_SOCKETS]>, error::Error<T::Error>> {
+        let mut result = arrayvec::ArrayVec::new();
+
+        for OpenSocket { socket, kind, .. } in self.sockets.clone() {
+            let (state, remote) = match kind {
+                types::SocketKind::TcpServer => (self.handler.get_server_state(socket)?, None),
+                types::SocketKind::TcpClient | types::SocketKind::Udp => (
+                    self.handler.get_client_state(socket)?,
+                    Some(self.handler.get_remote_data(socket)?),
+                ),
+            };
+            result.push(types::SocketInfo {
+                socket,
+                kind,
+                state,
+                remote,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Gets a free socket from the firmware.
+    ///
+    /// The firmware only reserves a socket once it is started, so until then it hands out the
+    /// same socket again; that counts as no socket being available.
+    fn allocate_socket(
+        &mut self,
+        kind: types::SocketKind,
+    ) -> Result<types::Socket, error::Error<T::Error>> {
+        let socket = self.handler.get_socket()?;
+        if self.open_socket(socket).is_some() {
+            return Err(error::Error::NoSocketAvailable);
+        }
+        self.track_socket(OpenSocket {
+            socket,
+            kind,
+            started: false,
+        });
+        Ok(socket)
+    }
+
+    /// Starts a socket from `allocate_socket` with `start`, unless the handle has `started` it
+    /// before.
+    ///
+    /// The firmware only reserves the socket if `start` succeeds, so it stays unstarted otherwise.
+    /// Fails with `Error::ClientInvalidated` if an incoming connection has taken the socket over
+    /// in the meantime.
+    fn start_socket(
+        &mut self,
+        socket: types::Socket,
+        started: &mut bool,
+        start: impl FnOnce(&mut handler::Handler<T>) -> Result<(), error::Error<T::Error>>,
+    ) -> Result<(), error::Error<T::Error>> {
+        if *started {
+            return start(&mut self.handler);
+        }
+
+        let index = self
+            .sockets
+            .iter()
+            .position(|open| open.socket == socket && !open.started)
+            .ok_or(error::Error::ClientInvalidated)?;
+        start(&mut self.handler)?;
+        self.sockets[index].started = true;
+        *started = true;
+        Ok(())
+    }
+
+    fn track_socket(&mut self, open: OpenSocket) {
+        self.release_socket(open.socket);
+        if self.sockets.try_push(open).is_err() {
+            log::warn!("too many open sockets to track socket {}", open.socket.0);
+        }
+    }
+
+    fn open_socket(&self, socket: types::Socket) -> Option<OpenSocket> {
+        self.sockets
+            .iter()
+            .copied()
+            .find(|open| open.socket == socket)
+    }
+
+    fn release_socket(&mut self, socket: types::Socket) {
+        self.sockets.retain(|open| open.socket != socket);
+    }
+
+    /// Releases a socket that was never started, unless an incoming connection has taken it over.
+    fn release_unstarted_socket(&mut self, socket: types::Socket) {
+        self.sockets
+            .retain(|open| open.socket != socket || open.started);
+    }
+}
+
+impl<T> Server<T>
+where
+    T: transport::Transport,
+{
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn state(&mut self, wifi: &mut Wifi<T>) -> Result<types::TcpState, error::Error<T::Error>> {
+        self.check(wifi)?;
+        wifi.handler.get_server_state(self.socket)
+    }
+
+    /// Returns the next incoming connection, or `WouldBlock` if there is none yet.
+    pub fn accept(&mut self, wifi: &mut Wifi<T>) -> nb::Result<Client<T>, error::Error<T::Error>> {
+        self.accept_with_buffer(wifi)
+    }
+
+    /// Like `accept`, but with an `N` byte receive buffer instead of the default one.
+    pub fn accept_with_buffer<const N: usize>(
+        &mut self,
+        wifi: &mut Wifi<T>,
+    ) -> nb::Result<Client<T, N>, error::Error<T::Error>> {
+        self.check(wifi)?;
+
+        let socket = match wifi.handler.avail_server(self.socket)? {
+            Some(socket) => socket,
+            None => return Err(nb::Error::WouldBlock),
+        };
+
+        match wifi.open_socket(socket) {
+            // The firmware keeps reporting accepted clients for as long as they have unread data
+            Some(open) if open.started => return Err(nb::Error::WouldBlock),
+            // The handle that allocated it can't use it anymore; `start_socket` will tell it so
+            Some(_) => log::warn!(
+                "incoming connection took over unstarted socket {}",
+                socket.0
+            ),
+            None => {}
+        }
+        wifi.track_socket(OpenSocket {
+            socket,
+            kind: types::SocketKind::TcpClient,
+            started: true,
+        });
+
+        Ok(Client::new(socket, wifi.id, self.generation, true))
+    }
+
+    /// Stops listening; clients accepted earlier stay connected.
+    pub fn close(self, wifi: &mut Wifi<T>) -> Result<(), error::Error<T::Error>> {
+        match self.check(wifi) {
+            Ok(()) => {}
+            Err(error::Error::ClientInvalidated) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        // Nothing could release the socket after this, so don't keep it on failure
+        let result = wifi.handler.stop_client(self.socket);
+        wifi.release_socket(self.socket);
+        result
+    }
+
+    fn check(&self, wifi: &Wifi<T>) -> Result<(), error::Error<T::Error>> {
+        wifi.check_handle(self.wifi_id, self.generation)
+    }
 }
 
-impl<T> Client<T>
+impl<T, const N: usize> Client<T, N>
 where
     T: transport::Transport,
 {
+    fn new(socket: types::Socket, wifi_id: usize, generation: u32, started: bool) -> Self {
+        let closed = false;
+        let buffer_offset = 0;
+        let buffer_len = 0;
+        let buffer = [0; N];
+        let phantom = marker::PhantomData;
+        Client {
+            socket,
+            wifi_id,
+            generation,
+            started,
+            closed,
+            buffer_offset,
+            buffer_len,
+            buffer,
+            phantom,
+        }
+    }
+
     pub fn connect_ipv4(
         &mut self,
         wifi: &mut Wifi<T>,
@@ -190,15 +929,42 @@ where
         port: u16,
         protocol_mode: types::ProtocolMode,
     ) -> Result<(), error::Error<T::Error>> {
-        wifi.handler
-            .start_client_by_ip(ip, port, self.socket, protocol_mode)
+        wifi.check_client(self)?;
+        let socket = self.socket;
+        wifi.start_socket(socket, &mut self.started, |handler| {
+            handler.start_client_by_ip(ip, port, socket, protocol_mode)
+        })
     }
 
+    /// Connects to a host by name, resolved by the firmware.
+    ///
+    /// With `ProtocolMode::Tls` this sends SNI and checks the server certificate against
+    /// `hostname`; a failed handshake is reported as `Error::TlsHandshake`.
+    pub fn connect_host(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        hostname: &str,
+        port: u16,
+        protocol_mode: types::ProtocolMode,
+    ) -> Result<(), error::Error<T::Error>> {
+        wifi.check_client(self)?;
+        if hostname.len() > usize::from(u8::MAX) {
+            return Err(error::Error::DataTooLong);
+        }
+        wifi.require(types::Capability::HostnameConnect)?;
+        let socket = self.socket;
+        wifi.start_socket(socket, &mut self.started, |handler| {
+            handler.start_client_by_host(hostname, port, socket, protocol_mode)
+        })
+    }
+
+    #[allow(clippy::legacy_numeric_constants)]
     pub fn send(
         &mut self,
         wifi: &mut Wifi<T>,
         data: &[u8],
     ) -> Result<usize, error::Error<T::Error>> {
+        wifi.check_client(self)?;
         let len = data.len().min(u16::max_value() as usize);
         let sent = wifi.handler.send_data(self.socket, &data[..len])?;
         wifi.handler.check_data_sent(self.socket)?;
@@ -218,31 +984,268 @@ where
     }
 
     pub fn state(&mut self, wifi: &mut Wifi<T>) -> Result<types::TcpState, error::Error<T::Error>> {
+        wifi.check_client(self)?;
         wifi.handler.get_client_state(self.socket)
     }
 
+    /// Closes the connection and waits for the firmware to release the socket.
+    ///
+    /// Clients invalidated by a disconnect or reset have no socket left to close, so this returns
+    /// right away for them.
+    pub fn close(
+        mut self,
+        wifi: &mut Wifi<T>,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        match wifi.check_client(&self) {
+            Ok(()) => {}
+            Err(error::Error::ClientInvalidated) => {
+                self.closed = true;
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+
+        // The handle is gone either way, so don't warn about a leak on drop
+        self.closed = true;
+        if !self.started {
+            wifi.release_unstarted_socket(self.socket);
+            return Ok(());
+        }
+        // Nothing could release the socket after this, so don't keep it on failure
+        let result = wifi.handler.stop_client(self.socket);
+        wifi.release_socket(self.socket);
+        result?;
+
+        self.await_tcp_state(wifi, types::TcpState::Closed, timeout)
+    }
+
+    /// Connects and waits until the connection is established.
+    ///
+    /// Fails with `Error::ConnectRefused` if the connection attempt ends without being
+    /// established, and with `Error::ConnectTimeout` if it is still pending after `timeout`.
+    pub fn connect_ipv4_timeout(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        ip: no_std_net::Ipv4Addr,
+        port: u16,
+        protocol_mode: types::ProtocolMode,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.connect_ipv4(wifi, ip, port, protocol_mode)?;
+        self.await_connected(wifi, timeout)
+    }
+
+    /// Waits for a connection started with `connect_ipv4` or `connect_host` to be established.
+    pub fn await_connected(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        let state = match self.await_tcp_state_matching(
+            wifi,
+            |state| !matches!(state, types::TcpState::SynSent | types::TcpState::SynRcvd),
+            timeout,
+        ) {
+            Ok(state) => state,
+            Err(error::Error::TcpStateTimeout(state)) => {
+                return Err(error::Error::ConnectTimeout(state))
+            }
+            Err(err) => return Err(err),
+        };
+
+        match state {
+            types::TcpState::Established => Ok(()),
+            state => Err(error::Error::ConnectRefused(state)),
+        }
+    }
+
+    /// Polls the connection until it reaches `tcp_state`, failing with
+    /// `Error::TcpStateTimeout` if it doesn't within `timeout`.
+    pub fn await_tcp_state(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        tcp_state: types::TcpState,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.await_tcp_state_matching(wifi, |state| state == tcp_state, timeout)?;
+        Ok(())
+    }
+
+    fn await_tcp_state_matching(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        predicate: impl Fn(types::TcpState) -> bool,
+        timeout: time::Duration,
+    ) -> Result<types::TcpState, error::Error<T::Error>> {
+        const POLL_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+        wifi.check_client(self)?;
+
+        let mut total_time = time::Duration::new(0, 0);
+        loop {
+            let state = wifi.handler.get_client_state(self.socket)?;
+            if predicate(state) {
+                return Ok(state);
+            }
+            if total_time > timeout {
+                return Err(error::Error::TcpStateTimeout(state));
+            }
+
+            wifi.handler.delay(POLL_INTERVAL)?;
+            // TODO: don't assume the actual SPI transfer takes 0 time :)
+            total_time += POLL_INTERVAL;
+        }
+    }
+
+    /// The number of bytes that can be received without waiting, including already buffered ones.
+    pub fn available(&mut self, wifi: &mut Wifi<T>) -> Result<usize, error::Error<T::Error>> {
+        wifi.check_client(self)?;
+        let buffered = self.buffer_len - self.buffer_offset;
+        let pending = usize::from(wifi.handler.avail_data(self.socket)?);
+        Ok(buffered + pending)
+    }
+
+    /// Receives data; returns 0 if the firmware has nothing buffered for this socket.
     pub fn recv(
         &mut self,
         wifi: &mut Wifi<T>,
         data: &mut [u8],
     ) -> Result<usize, error::Error<T::Error>> {
-        if self.buffer_offset >= self.buffer.len() {
-            self.buffer.clear();
-            self.buffer
-                .try_extend_from_slice(&[0; BUFFER_CAPACITY])
-                .unwrap();
-            let recv_len = wifi
-                .handler
-                .get_data_buf(self.socket, self.buffer.as_mut())?;
-            self.buffer.truncate(recv_len);
-            self.buffer_offset = 0;
-            log::debug!("fetched new buffer of len {}", self.buffer.len());
-        }
-
-        let len = data.len().min(self.buffer.len() - self.buffer_offset);
+        wifi.check_client(self)?;
+        if self.buffer_offset < self.buffer_len {
+            return Ok(self.take_buffered(data));
+        }
+
+        let available = usize::from(wifi.handler.avail_data(self.socket)?);
+        if available == 0 {
+            return Ok(0);
+        }
+        self.recv_pending(wifi, data, available)
+    }
+
+    /// Receives data without waiting for it.
+    ///
+    /// Returns `WouldBlock` if nothing has arrived yet, and `Ok(0)` once the peer has closed the
+    /// connection and all data has been read.
+    pub fn try_recv(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        data: &mut [u8],
+    ) -> nb::Result<usize, error::Error<T::Error>> {
+        wifi.check_client(self)?;
+        if self.buffer_offset < self.buffer_len {
+            return Ok(self.take_buffered(data));
+        }
+
+        match self.pending(wifi)? {
+            Some(available) => Ok(self.recv_pending(wifi, data, available)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Refills the buffer if it has been read completely, with the same `WouldBlock` and end of
+    /// stream semantics as `try_recv`; an empty `buffered()` afterwards means end of stream.
+    ///
+    /// Fails with `Error::NoBuffer` if `N == 0`, as there is nothing to fill.
+    pub fn try_fill_buffer(
+        &mut self,
+        wifi: &mut Wifi<T>,
+    ) -> nb::Result<(), error::Error<T::Error>> {
+        wifi.check_client(self)?;
+        if N == 0 {
+            return Err(nb::Error::Other(error::Error::NoBuffer));
+        }
+        if self.buffer_offset < self.buffer_len {
+            return Ok(());
+        }
+
+        match self.pending(wifi)? {
+            Some(available) => Ok(self.fill_buffer(wifi, available)?),
+            None => Ok(()),
+        }
+    }
+
+    /// The received data that hasn't been read yet.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer[self.buffer_offset..self.buffer_len]
+    }
+
+    /// Marks `amt` bytes of `buffered()` as read.
+    pub fn consume(&mut self, amt: usize) {
+        self.buffer_offset = (self.buffer_offset + amt).min(self.buffer_len);
+    }
+
+    /// Waits for the firmware to confirm that everything sent so far has gone out.
+    pub fn flush(&mut self, wifi: &mut Wifi<T>) -> Result<(), error::Error<T::Error>> {
+        wifi.check_client(self)?;
+        wifi.handler.check_data_sent(self.socket)
+    }
+
+    /// How much data the firmware has pending, or `None` at end of stream.
+    fn pending(&mut self, wifi: &mut Wifi<T>) -> nb::Result<Option<usize>, error::Error<T::Error>> {
+        let available = usize::from(wifi.handler.avail_data(self.socket)?);
+        if available > 0 {
+            return Ok(Some(available));
+        }
+        if !self.peer_closed(wifi)? {
+            return Err(nb::Error::WouldBlock);
+        }
+        // The last data may have arrived together with the FIN, after the first check
+        match usize::from(wifi.handler.avail_data(self.socket)?) {
+            0 => Ok(None),
+            available => Ok(Some(available)),
+        }
+    }
+
+    fn peer_closed(&mut self, wifi: &mut Wifi<T>) -> Result<bool, error::Error<T::Error>> {
+        Ok(matches!(
+            wifi.handler.get_client_state(self.socket)?,
+            types::TcpState::CloseWait | types::TcpState::Closed
+        ))
+    }
+
+    /// Receives data the firmware has pending, bypassing the buffer when `data` can hold at least
+    /// as much as the buffer would.
+    fn recv_pending(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        data: &mut [u8],
+        available: usize,
+    ) -> Result<usize, error::Error<T::Error>> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        if data.len() >= available.min(N) {
+            let len = data.len().min(available).min(MAX_TRANSFER_LEN);
+            return wifi.handler.get_data_buf(self.socket, &mut data[..len]);
+        }
+
+        self.fill_buffer(wifi, available)?;
+        Ok(self.take_buffered(data))
+    }
+
+    fn fill_buffer(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        available: usize,
+    ) -> Result<(), error::Error<T::Error>> {
+        // The buffer was zeroed once on creation; the firmware overwrites what it returns
+        let len = available.min(N).min(MAX_TRANSFER_LEN);
+        self.buffer_len = wifi
+            .handler
+            .get_data_buf(self.socket, &mut self.buffer[..len])?;
+        self.buffer_offset = 0;
+        log::debug!("fetched new buffer of len {}", self.buffer_len);
+        Ok(())
+    }
+
+    fn take_buffered(&mut self, data: &mut [u8]) -> usize {
+        let len = data.len().min(self.buffer_len - self.buffer_offset);
         data[..len].copy_from_slice(&self.buffer[self.buffer_offset..self.buffer_offset + len]);
         self.buffer_offset += len;
-        Ok(len)
+        len
     }
 
     pub fn recv_exact(
@@ -257,3 +1260,234 @@ where
         Ok(())
     }
 }
+
+impl<T, const N: usize> Drop for Client<T, N> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        if !self.closed {
+            log::warn!(
+                "client on socket {} dropped without calling close(); the socket is leaked",
+                self.socket.0
+            );
+        }
+    }
+}
+
+/// Only loads and stores, since targets like thumbv6m have no compare-and-swap; `Wifi`s are
+/// created during start-up, not concurrently.
+fn next_wifi_id() -> usize {
+    let id = NEXT_WIFI_ID.load(atomic::Ordering::Relaxed);
+    NEXT_WIFI_ID.store(id.wrapping_add(1), atomic::Ordering::Relaxed);
+    id
+}
+
+fn copy_bytes<A, E>(bytes: &[u8]) -> Result<arrayvec::ArrayVec<A>, error::Error<E>>
+where
+    A: arrayvec::Array<Item = u8>,
+{
+    let mut result = arrayvec::ArrayVec::new();
+    result
+        .try_extend_from_slice(bytes)
+        .map_err(|_| error::Error::DataTooLong)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::testing::FakeTransport;
+
+    #[test]
+    fn unstarted_sockets_are_not_handed_out_twice() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+            .expect(Command::GetSocketCmd, &[&[1]]);
+        let mut wifi = Wifi::new(transport);
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let mut first: Client<_, 0> = wifi.new_client_with_buffer().unwrap();
+        assert!(matches!(
+            wifi.new_udp_socket(),
+            Err(error::Error::NoSocketAvailable)
+        ));
+
+        first
+            .connect_ipv4(&mut wifi, ip, 80, types::ProtocolMode::Tcp)
+            .unwrap();
+        wifi.new_udp_socket().unwrap();
+        assert!(wifi.open_socket(types::Socket(1)).is_some());
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn accept_takes_over_unstarted_socket() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartServerTcpCmd, &[&[1]])
+            .expect(Command::GetSocketCmd, &[&[1]])
+            .expect(Command::AvailDataTcpCmd, &[&[1, 0]]);
+        let mut wifi = Wifi::new(transport);
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let mut server = wifi.new_server(80).unwrap();
+        let mut pending: Client<_, 0> = wifi.new_client_with_buffer().unwrap();
+        let accepted: Client<_, 0> = server.accept_with_buffer(&mut wifi).unwrap();
+        assert_eq!(types::Socket(1), accepted.socket);
+
+        // The pending client neither starts nor stops the accepted connection
+        assert!(matches!(
+            pending.connect_ipv4(&mut wifi, ip, 80, types::ProtocolMode::Tcp),
+            Err(error::Error::ClientInvalidated)
+        ));
+        pending
+            .close(&mut wifi, time::Duration::from_secs(1))
+            .unwrap();
+        assert!(wifi.open_socket(types::Socket(1)).unwrap().started);
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn handles_are_checked_against_their_wifi() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartServerTcpCmd, &[&[1]])
+            .expect(Command::GetSocketCmd, &[&[1]]);
+        let mut wifi = Wifi::new(transport);
+        let mut other = Wifi::new(FakeTransport::new());
+
+        let mut server = wifi.new_server(80).unwrap();
+        let mut socket = wifi.new_udp_socket().unwrap();
+        assert!(matches!(
+            server.accept(&mut other),
+            Err(nb::Error::Other(error::Error::WrongWifi))
+        ));
+        assert!(matches!(
+            socket.bind(&mut other, 53),
+            Err(error::Error::WrongWifi)
+        ));
+        assert!(matches!(
+            socket.close(&mut other),
+            Err(error::Error::WrongWifi)
+        ));
+        assert!(matches!(
+            server.close(&mut other),
+            Err(error::Error::WrongWifi)
+        ));
+    }
+
+    #[test]
+    fn failed_disconnect_invalidates_handles() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+            .expect(Command::StopClientTcpCmd, &[&[1]])
+            .expect(Command::DisconnectCmd, &[&[0]])
+            .expect(Command::GetSocketCmd, &[&[0]]);
+        let mut wifi = Wifi::new(transport);
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let mut client: Client<_, 0> = wifi.new_client_with_buffer().unwrap();
+        client
+            .connect_ipv4(&mut wifi, ip, 80, types::ProtocolMode::Tcp)
+            .unwrap();
+        assert!(matches!(
+            wifi.disconnect(time::Duration::from_secs(1)),
+            Err(error::Error::Disconnect)
+        ));
+        assert!(matches!(
+            client.state(&mut wifi),
+            Err(error::Error::ClientInvalidated)
+        ));
+        wifi.new_udp_socket().unwrap();
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn configure_access_point() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::SetApNetCmd, &[&[1]])
+            .expect(Command::SetApPassphraseCmd, &[&[1]])
+            .expect(Command::GetConnStatusCmd, &[&[7]]);
+        let mut wifi = Wifi::new(transport);
+
+        let open = types::AccessPointConfig {
+            ssid: b"ap",
+            password: b"",
+            channel: 6,
+        };
+        wifi.configure(types::Config::AccessPoint(open), None)
+            .unwrap();
+        let secured = types::AccessPointConfig {
+            ssid: b"ap",
+            password: b"password",
+            channel: 11,
+        };
+        wifi.configure(
+            types::Config::AccessPoint(secured),
+            Some(time::Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert!(matches!(
+            wifi.reconnect(None),
+            Err(error::Error::NotConfigured)
+        ));
+
+        let sent = &wifi.handler.transport().sent;
+        assert_eq!(&b"\x02\x03ap\0\x01\x06"[..], &sent[0].1[..]);
+        assert_eq!(&b"\x03\x03ap\0\x09password\0\x01\x0b"[..], &sent[1].1[..]);
+    }
+
+    #[test]
+    fn failed_connect_leaves_socket_unstarted() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[0]])
+            .expect(Command::GetSocketCmd, &[&[0]]);
+        let mut wifi = Wifi::new(transport);
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let mut client: Client<_, 0> = wifi.new_client_with_buffer().unwrap();
+        assert!(matches!(
+            client.connect_ipv4(&mut wifi, ip, 443, types::ProtocolMode::Tls),
+            Err(error::Error::TlsHandshake)
+        ));
+        // Closing doesn't stop a socket the firmware never reserved
+        client
+            .close(&mut wifi, time::Duration::from_secs(1))
+            .unwrap();
+        wifi.new_udp_socket().unwrap();
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn failed_stop_releases_socket() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+            .expect(Command::StopClientTcpCmd, &[&[0]])
+            .expect(Command::GetSocketCmd, &[&[0]]);
+        let mut wifi = Wifi::new(transport);
+        let ip = no_std_net::Ipv4Addr::new(10, 0, 0, 1);
+
+        let mut client: Client<_, 0> = wifi.new_client_with_buffer().unwrap();
+        client
+            .connect_ipv4(&mut wifi, ip, 80, types::ProtocolMode::Tcp)
+            .unwrap();
+        assert!(matches!(
+            client.close(&mut wifi, time::Duration::from_secs(1)),
+            Err(error::Error::StopClient)
+        ));
+        wifi.new_udp_socket().unwrap();
+        assert!(wifi.handler.transport().is_done());
+    }
+}
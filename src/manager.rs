@@ -0,0 +1,417 @@
+//! A pollable connection manager that keeps the module joined to its network.
+
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::Wifi;
+use core::time;
+
+const STATE_POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ManagerConfig {
+    /// How long a single join attempt may take before it counts as failed.
+    pub connect_timeout: time::Duration,
+    pub initial_backoff: time::Duration,
+    pub max_backoff: time::Duration,
+    /// Hard-reset the module after this many consecutive failed attempts.
+    ///
+    /// The reset itself blocks for as long as the transport's reset sequence takes.
+    pub reset_after: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    Idle,
+    Connecting { attempt: u32 },
+    Connected,
+    Backoff { attempt: u32, until: time::Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct ConnectionManager {
+    config: ManagerConfig,
+    phase: Phase,
+    deadline: time::Duration,
+    failures: u32,
+    resets: u32,
+    rng: u32,
+    last_state_poll: Option<time::Duration>,
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: time::Duration::from_secs(15),
+            initial_backoff: time::Duration::from_secs(1),
+            max_backoff: time::Duration::from_secs(300),
+            reset_after: Some(5),
+        }
+    }
+}
+
+impl ConnectionManager {
+    /// Creates a manager in the `Idle` phase.
+    ///
+    /// The seed drives the backoff jitter; something device-specific like the MAC address keeps
+    /// a fleet of devices from retrying in lockstep.
+    pub fn new(config: ManagerConfig, seed: u32) -> Self {
+        let phase = Phase::Idle;
+        let deadline = time::Duration::new(0, 0);
+        let failures = 0;
+        let resets = 0;
+        // xorshift gets stuck on zero
+        let rng = if seed == 0 { 0x9e37_79b9 } else { seed };
+        let last_state_poll = None;
+        Self {
+            config,
+            phase,
+            deadline,
+            failures,
+            resets,
+            rng,
+            last_state_poll,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// The number of consecutive failed join attempts.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// The number of times the module has been reset by this manager.
+    pub fn resets(&self) -> u32 {
+        self.resets
+    }
+
+    /// Advances the state machine without blocking on the connection.
+    ///
+    /// `now` is any monotonic timestamp, e.g. time since boot. The network to join is the one
+    /// last passed to `Wifi::configure`; calling this before that fails with
+    /// `Error::NotConfigured`.
+    pub fn poll<T>(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        now: time::Duration,
+    ) -> Result<Phase, error::Error<T::Error>>
+    where
+        T: transport::Transport,
+    {
+        match self.phase {
+            Phase::Idle => self.start_attempt(wifi, now, 1)?,
+            Phase::Connecting { attempt } => {
+                let state = wifi.connection_state()?;
+                match state {
+                    types::ConnectionState::Connected => {
+                        log::info!("connected after {} attempt(s)", attempt);
+                        self.failures = 0;
+                        self.phase = Phase::Connected;
+                        self.last_state_poll = Some(now);
+                    }
+                    types::ConnectionState::ConnectFailed
+                    | types::ConnectionState::NoSsidAvail
+                    | types::ConnectionState::ConnectionLost => {
+                        log::warn!("join attempt {} failed: {:?}", attempt, state);
+                        self.fail(wifi, now, attempt)?;
+                    }
+                    _ if now >= self.deadline => {
+                        log::warn!("join attempt {} timed out in {:?}", attempt, state);
+                        self.fail(wifi, now, attempt)?;
+                    }
+                    _ => {}
+                }
+            }
+            Phase::Connected => {
+                let due = match self.last_state_poll {
+                    None => true,
+                    Some(last) => now < last || now - last >= STATE_POLL_INTERVAL,
+                };
+                if due {
+                    let state = wifi.connection_state()?;
+                    self.last_state_poll = Some(now);
+                    if state != types::ConnectionState::Connected {
+                        log::warn!("connection lost: {:?}", state);
+                        self.start_attempt(wifi, now, 1)?;
+                    }
+                }
+            }
+            Phase::Backoff { attempt, until } => {
+                if now >= until {
+                    self.start_attempt(wifi, now, attempt)?;
+                }
+            }
+        }
+
+        Ok(self.phase)
+    }
+
+    fn start_attempt<T>(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        now: time::Duration,
+        attempt: u32,
+    ) -> Result<(), error::Error<T::Error>>
+    where
+        T: transport::Transport,
+    {
+        match wifi.reconnect(None) {
+            Ok(()) => {}
+            // Retrying can't help when the module is unreachable or nothing is configured
+            Err(err @ error::Error::Transport(_)) | Err(err @ error::Error::NotConfigured) => {
+                return Err(err)
+            }
+            Err(_) => {
+                log::warn!("join attempt {} was rejected by the module", attempt);
+                return self.fail(wifi, now, attempt);
+            }
+        }
+        self.deadline = now + self.config.connect_timeout;
+        self.phase = Phase::Connecting { attempt };
+        Ok(())
+    }
+
+    fn fail<T>(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        now: time::Duration,
+        attempt: u32,
+    ) -> Result<(), error::Error<T::Error>>
+    where
+        T: transport::Transport,
+    {
+        self.failures += 1;
+
+        if let Some(reset_after) = self.config.reset_after {
+            if reset_after != 0 && self.failures % reset_after == 0 {
+                log::warn!("resetting module after {} failed attempts", self.failures);
+                wifi.reset()?;
+                self.resets += 1;
+            }
+        }
+
+        let until = now + self.backoff();
+        self.phase = Phase::Backoff {
+            attempt: attempt + 1,
+            until,
+        };
+        Ok(())
+    }
+
+    /// Exponential backoff with "equal jitter": half of the delay is fixed, the other half random.
+    fn backoff(&mut self) -> time::Duration {
+        let exponent = self.failures.saturating_sub(1).min(16);
+        let delay = self
+            .config
+            .initial_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.config.max_backoff)
+            .min(self.config.max_backoff);
+
+        let half = delay / 2;
+        let jitter_millis = half.as_millis() as u32;
+        let jitter = if jitter_millis == 0 {
+            0
+        } else {
+            self.next_random() % jitter_millis
+        };
+
+        half + time::Duration::from_millis(u64::from(jitter))
+    }
+
+    fn next_random(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::testing::FakeTransport;
+
+    const CONNECTED: u8 = 3;
+    const CONNECT_FAILED: u8 = 4;
+
+    fn config(reset_after: Option<u32>) -> ManagerConfig {
+        ManagerConfig {
+            connect_timeout: time::Duration::from_secs(10),
+            initial_backoff: time::Duration::from_secs(1),
+            max_backoff: time::Duration::from_secs(8),
+            reset_after,
+        }
+    }
+
+    /// A module configured with an open network, with the configure call already scripted.
+    fn configured_wifi(transport: FakeTransport) -> Wifi<FakeTransport> {
+        let mut wifi = Wifi::new(transport);
+        let network = types::NetworkConfig::Open { ssid: b"net" };
+        wifi.configure(
+            types::Config::Station(types::StationConfig { network }),
+            None,
+        )
+        .unwrap();
+        wifi
+    }
+
+    /// A module on which every join attempt fails straight away.
+    fn failing_wifi(attempts: usize) -> Wifi<FakeTransport> {
+        let mut transport = FakeTransport::new();
+        transport.expect(Command::SetNetCmd, &[&[1]]);
+        for _ in 0..attempts {
+            transport
+                .expect(Command::SetNetCmd, &[&[1]])
+                .expect(Command::GetConnStatusCmd, &[&[CONNECT_FAILED]]);
+        }
+        configured_wifi(transport)
+    }
+
+    /// Runs one failing join attempt and returns the backoff that follows it.
+    fn fail_attempt(
+        manager: &mut ConnectionManager,
+        wifi: &mut Wifi<FakeTransport>,
+        now: time::Duration,
+    ) -> time::Duration {
+        match manager.poll(wifi, now).unwrap() {
+            Phase::Connecting { .. } => {}
+            phase => panic!("expected to be connecting, was {:?}", phase),
+        }
+        match manager.poll(wifi, now).unwrap() {
+            Phase::Backoff { until, .. } => until - now,
+            phase => panic!("expected to back off, was {:?}", phase),
+        }
+    }
+
+    #[test]
+    fn retries_after_backoff() {
+        let mut wifi = failing_wifi(2);
+        let mut manager = ConnectionManager::new(config(None), 1);
+        let start = time::Duration::from_secs(100);
+
+        assert_eq!(Phase::Idle, manager.phase());
+        assert_eq!(
+            Phase::Connecting { attempt: 1 },
+            manager.poll(&mut wifi, start).unwrap()
+        );
+        let until = match manager.poll(&mut wifi, start).unwrap() {
+            Phase::Backoff { attempt: 2, until } => until,
+            phase => panic!("expected to back off, was {:?}", phase),
+        };
+        assert!(until > start);
+        assert_eq!(1, manager.failures());
+
+        // Nothing happens until the backoff has passed
+        assert_eq!(
+            Phase::Backoff { attempt: 2, until },
+            manager
+                .poll(&mut wifi, until - time::Duration::from_millis(1))
+                .unwrap()
+        );
+        assert_eq!(
+            Phase::Connecting { attempt: 2 },
+            manager.poll(&mut wifi, until).unwrap()
+        );
+        assert!(matches!(
+            manager.poll(&mut wifi, until).unwrap(),
+            Phase::Backoff { attempt: 3, .. }
+        ));
+        assert_eq!(2, manager.failures());
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let config = config(None);
+        let mut wifi = failing_wifi(10);
+        let mut manager = ConnectionManager::new(config, 1);
+        let mut now = time::Duration::new(0, 0);
+
+        for failures in 1..=10 {
+            let backoff = fail_attempt(&mut manager, &mut wifi, now);
+            let delay = (config.initial_backoff * (1 << (failures - 1))).min(config.max_backoff);
+            assert!(
+                backoff >= delay / 2 && backoff < delay,
+                "backoff {:?} after {} failures",
+                backoff,
+                failures
+            );
+            now += backoff;
+        }
+    }
+
+    #[test]
+    fn resets_every_reset_after_failures() {
+        let mut wifi = failing_wifi(6);
+        let mut manager = ConnectionManager::new(config(Some(2)), 1);
+        let mut now = time::Duration::new(0, 0);
+
+        for failures in 1..=6 {
+            now += fail_attempt(&mut manager, &mut wifi, now);
+            assert_eq!(failures / 2, manager.resets());
+        }
+        assert_eq!(3, wifi.handler.transport().resets);
+    }
+
+    #[test]
+    fn reset_after_zero_never_resets() {
+        let mut wifi = failing_wifi(5);
+        let mut manager = ConnectionManager::new(config(Some(0)), 1);
+        let mut now = time::Duration::new(0, 0);
+
+        for _ in 0..5 {
+            now += fail_attempt(&mut manager, &mut wifi, now);
+        }
+        assert_eq!(0, manager.resets());
+        assert_eq!(0, wifi.handler.transport().resets);
+    }
+
+    #[test]
+    fn rejected_join_counts_as_failure() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::SetNetCmd, &[&[1]])
+            .expect(Command::SetNetCmd, &[&[0]]);
+        let mut wifi = configured_wifi(transport);
+        let mut manager = ConnectionManager::new(config(Some(1)), 1);
+
+        assert!(matches!(
+            manager.poll(&mut wifi, time::Duration::new(0, 0)).unwrap(),
+            Phase::Backoff { attempt: 2, .. }
+        ));
+        assert_eq!(1, manager.failures());
+        assert_eq!(1, manager.resets());
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn connected_state_is_polled_at_interval() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::SetNetCmd, &[&[1]])
+            .expect(Command::SetNetCmd, &[&[1]])
+            .expect(Command::GetConnStatusCmd, &[&[CONNECTED]])
+            .expect(Command::GetConnStatusCmd, &[&[CONNECTED]]);
+        let mut wifi = configured_wifi(transport);
+        let mut manager = ConnectionManager::new(config(None), 1);
+        let start = time::Duration::from_secs(1);
+
+        manager.poll(&mut wifi, start).unwrap();
+        assert_eq!(Phase::Connected, manager.poll(&mut wifi, start).unwrap());
+        // Polls in between don't talk to the module
+        for millis in [100, 250, 499].iter() {
+            let now = start + time::Duration::from_millis(*millis);
+            assert_eq!(Phase::Connected, manager.poll(&mut wifi, now).unwrap());
+        }
+        let now = start + STATE_POLL_INTERVAL;
+        assert_eq!(Phase::Connected, manager.poll(&mut wifi, now).unwrap());
+        assert!(wifi.handler.transport().is_done());
+    }
+}
@@ -0,0 +1,575 @@
+//! `embedded-nal` stack implementation for `Wifi`.
+//!
+//! The firmware only speaks IPv4; IPv6 addresses are rejected with `Error::Ipv6Unsupported`.
+
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::udp;
+use crate::Client;
+use crate::Server;
+use crate::Wifi;
+use core::fmt;
+use core::net;
+use core::time;
+
+/// How long `TcpClientStack::close` waits for the firmware to release the socket.
+const CLOSE_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// A TCP socket as handed out by `TcpClientStack::socket`.
+///
+/// The firmware socket is only allocated once the socket is connected or starts listening.
+/// Connected sockets have no receive buffer of their own; `receive` reads straight into the
+/// caller's buffer.
+#[derive(Debug)]
+pub struct TcpSocket<T> {
+    state: TcpSocketState<T>,
+}
+
+#[derive(Debug)]
+enum TcpSocketState<T> {
+    Unconnected { local_port: Option<u16> },
+    Connected(Client<T, 0>),
+    Listening(Server<T>),
+}
+
+/// A UDP socket as handed out by `UdpClientStack::socket`.
+///
+/// Like with `TcpSocket`, the firmware socket is only allocated once the socket is bound or sends
+/// something.
+#[derive(Debug)]
+pub struct UdpSocket<T> {
+    state: UdpSocketState<T>,
+}
+
+#[derive(Debug)]
+enum UdpSocketState<T> {
+    Unallocated {
+        remote: Option<(no_std_net::Ipv4Addr, u16)>,
+    },
+    Allocated(udp::UdpSocket<T>),
+}
+
+impl<T> TcpSocket<T> {
+    fn new(state: TcpSocketState<T>) -> Self {
+        Self { state }
+    }
+}
+
+impl<T> UdpSocket<T>
+where
+    T: transport::Transport,
+{
+    fn new(state: UdpSocketState<T>) -> Self {
+        Self { state }
+    }
+
+    fn allocated(
+        &mut self,
+        wifi: &mut Wifi<T>,
+    ) -> Result<&mut udp::UdpSocket<T>, error::Error<T::Error>> {
+        if let UdpSocketState::Unallocated { remote } = self.state {
+            let mut socket = wifi.new_udp_socket()?;
+            if let Some((ip, port)) = remote {
+                socket.connect(ip, port);
+            }
+            self.state = UdpSocketState::Allocated(socket);
+        }
+
+        match &mut self.state {
+            UdpSocketState::Allocated(socket) => Ok(socket),
+            UdpSocketState::Unallocated { .. } => unreachable!(),
+        }
+    }
+}
+
+impl<E> embedded_nal::TcpError for error::Error<E>
+where
+    E: fmt::Debug,
+{
+    fn kind(&self) -> embedded_nal::TcpErrorKind {
+        match self {
+            error::Error::NotConnected | error::Error::ClientInvalidated => {
+                embedded_nal::TcpErrorKind::PipeClosed
+            }
+            _ => embedded_nal::TcpErrorKind::Other,
+        }
+    }
+}
+
+impl<T> embedded_nal::TcpClientStack for Wifi<T>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    type TcpSocket = TcpSocket<T>;
+    type Error = error::Error<T::Error>;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        Ok(TcpSocket::new(TcpSocketState::Unconnected {
+            local_port: None,
+        }))
+    }
+
+    /// Starts connecting on the first call, then returns `WouldBlock` until the handshake is
+    /// done; a refused or failed connection is reported as `Error::ConnectRefused`.
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: net::SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        if let TcpSocketState::Unconnected { .. } = socket.state {
+            let (ip, port) = ipv4_addr(remote)?;
+            let mut client = self.new_client_with_buffer()?;
+            if let Err(err) = client.connect_ipv4(self, ip, port, types::ProtocolMode::Tcp) {
+                // The connect error is the one worth reporting
+                let _ = client.close(self, CLOSE_TIMEOUT);
+                return Err(nb::Error::Other(err));
+            }
+            socket.state = TcpSocketState::Connected(client);
+        }
+
+        let client = match &mut socket.state {
+            TcpSocketState::Connected(client) => client,
+            _ => return Err(nb::Error::Other(error::Error::InvalidSocketState)),
+        };
+
+        match client.state(self)? {
+            types::TcpState::Established => Ok(()),
+            types::TcpState::SynSent | types::TcpState::SynRcvd => Err(nb::Error::WouldBlock),
+            state => Err(nb::Error::Other(error::Error::ConnectRefused(state))),
+        }
+    }
+
+    fn send(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, Self::Error> {
+        let client = connected(socket)?;
+        match client.state(self)? {
+            types::TcpState::Established | types::TcpState::CloseWait => {
+                Ok(client.send(self, buffer)?)
+            }
+            types::TcpState::SynSent | types::TcpState::SynRcvd => Err(nb::Error::WouldBlock),
+            _ => Err(nb::Error::Other(error::Error::NotConnected)),
+        }
+    }
+
+    /// Returns `WouldBlock` while the firmware has no data for the socket, and `Ok(0)` once the
+    /// peer has closed the connection and everything has been read.
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        connected(socket)?.try_recv(self, buffer)
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+        match socket.state {
+            TcpSocketState::Unconnected { .. } => Ok(()),
+            TcpSocketState::Connected(client) => client.close(self, CLOSE_TIMEOUT),
+            TcpSocketState::Listening(server) => server.close(self),
+        }
+    }
+}
+
+impl<T> embedded_nal::TcpFullStack for Wifi<T>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    fn bind(&mut self, socket: &mut Self::TcpSocket, local_port: u16) -> Result<(), Self::Error> {
+        match &mut socket.state {
+            TcpSocketState::Unconnected { local_port: port } => {
+                *port = Some(local_port);
+                Ok(())
+            }
+            _ => Err(error::Error::InvalidSocketState),
+        }
+    }
+
+    fn listen(&mut self, socket: &mut Self::TcpSocket) -> Result<(), Self::Error> {
+        match socket.state {
+            TcpSocketState::Unconnected {
+                local_port: Some(port),
+            } => {
+                socket.state = TcpSocketState::Listening(self.new_server(port)?);
+                Ok(())
+            }
+            _ => Err(error::Error::InvalidSocketState),
+        }
+    }
+
+    fn accept(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+    ) -> nb::Result<(Self::TcpSocket, net::SocketAddr), Self::Error> {
+        let server = match &mut socket.state {
+            TcpSocketState::Listening(server) => server,
+            _ => return Err(nb::Error::Other(error::Error::InvalidSocketState)),
+        };
+
+        let client = server.accept_with_buffer(self)?;
+        let remote = self.handler.get_remote_data(client.socket)?;
+        Ok((
+            TcpSocket::new(TcpSocketState::Connected(client)),
+            socket_addr(remote),
+        ))
+    }
+}
+
+impl<T> embedded_nal::UdpClientStack for Wifi<T>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    type UdpSocket = UdpSocket<T>;
+    type Error = error::Error<T::Error>;
+
+    fn socket(&mut self) -> Result<Self::UdpSocket, Self::Error> {
+        Ok(UdpSocket::new(UdpSocketState::Unallocated { remote: None }))
+    }
+
+    /// Sets the default destination; replies to datagrams sent from a socket that wasn't bound
+    /// with `UdpFullStack::bind` arrive on whatever port the firmware sent from.
+    fn connect(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        remote: net::SocketAddr,
+    ) -> Result<(), Self::Error> {
+        let (ip, port) = ipv4_addr(remote)?;
+        match &mut socket.state {
+            UdpSocketState::Unallocated { remote } => *remote = Some((ip, port)),
+            UdpSocketState::Allocated(socket) => socket.connect(ip, port),
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+        Ok(socket.allocated(self)?.send(self, buffer)?)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<(usize, net::SocketAddr), Self::Error> {
+        let socket = match &mut socket.state {
+            UdpSocketState::Allocated(socket) => socket,
+            // Nothing can arrive before the socket is bound or has sent something
+            UdpSocketState::Unallocated { .. } => return Err(nb::Error::WouldBlock),
+        };
+        let (len, remote) = socket.recv_from(self, buffer)?;
+        Ok((len, socket_addr(remote)))
+    }
+
+    fn close(&mut self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+        match socket.state {
+            UdpSocketState::Unallocated { .. } => Ok(()),
+            UdpSocketState::Allocated(socket) => socket.close(self),
+        }
+    }
+}
+
+impl<T> embedded_nal::UdpFullStack for Wifi<T>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    fn bind(&mut self, socket: &mut Self::UdpSocket, local_port: u16) -> Result<(), Self::Error> {
+        socket.allocated(self)?.bind(self, local_port)
+    }
+
+    fn send_to(
+        &mut self,
+        socket: &mut Self::UdpSocket,
+        remote: net::SocketAddr,
+        buffer: &[u8],
+    ) -> nb::Result<(), Self::Error> {
+        let (ip, port) = ipv4_addr(remote)?;
+        Ok(socket.allocated(self)?.send_to(self, buffer, ip, port)?)
+    }
+}
+
+impl<T> embedded_nal::Dns for Wifi<T>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    type Error = error::Error<T::Error>;
+
+    /// Resolves through the firmware, which blocks until it has an answer.
+    fn get_host_by_name(
+        &mut self,
+        hostname: &str,
+        addr_type: embedded_nal::AddrType,
+    ) -> nb::Result<net::IpAddr, Self::Error> {
+        if addr_type == embedded_nal::AddrType::IPv6 {
+            return Err(nb::Error::Other(error::Error::Ipv6Unsupported));
+        }
+        let ip = self.resolve(hostname)?;
+        Ok(net::IpAddr::V4(net::Ipv4Addr::from(ip.octets())))
+    }
+
+    fn get_host_by_address(
+        &mut self,
+        _addr: net::IpAddr,
+        _result: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        Err(nb::Error::Other(error::Error::ReverseDnsUnsupported))
+    }
+}
+
+fn connected<T, E>(socket: &mut TcpSocket<T>) -> Result<&mut Client<T, 0>, error::Error<E>> {
+    match &mut socket.state {
+        TcpSocketState::Connected(client) => Ok(client),
+        _ => Err(error::Error::NotConnected),
+    }
+}
+
+fn ipv4_addr<E>(addr: net::SocketAddr) -> Result<(no_std_net::Ipv4Addr, u16), error::Error<E>> {
+    match addr {
+        net::SocketAddr::V4(addr) => Ok((addr.ip().octets().into(), addr.port())),
+        net::SocketAddr::V6(_) => Err(error::Error::Ipv6Unsupported),
+    }
+}
+
+fn socket_addr(remote: types::RemoteData) -> net::SocketAddr {
+    let ip = net::Ipv4Addr::from(remote.ip.octets());
+    net::SocketAddr::V4(net::SocketAddrV4::new(ip, remote.port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::testing::FakeTransport;
+    use embedded_nal::{TcpClientStack, TcpFullStack, UdpClientStack, UdpFullStack};
+
+    const NO_SOCKET: &[u8] = &[0xff, 0];
+
+    fn remote() -> net::SocketAddr {
+        net::SocketAddr::V4(net::SocketAddrV4::new(net::Ipv4Addr::new(10, 0, 0, 2), 80))
+    }
+
+    fn tcp_state(state: types::TcpState) -> u8 {
+        u8::from(state)
+    }
+
+    fn expect_connect(transport: &mut FakeTransport) -> &mut FakeTransport {
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::Established)]],
+            )
+    }
+
+    #[test]
+    fn connect_would_block_until_established() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::SynSent)]],
+            )
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::Established)]],
+            );
+        let mut wifi = Wifi::new(transport);
+
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        assert!(matches!(
+            TcpClientStack::connect(&mut wifi, &mut socket, remote()),
+            Err(nb::Error::WouldBlock)
+        ));
+        TcpClientStack::connect(&mut wifi, &mut socket, remote()).unwrap();
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn connect_refused_when_closed() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[1]])
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::Closed)]],
+            );
+        let mut wifi = Wifi::new(transport);
+
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        assert!(matches!(
+            TcpClientStack::connect(&mut wifi, &mut socket, remote()),
+            Err(nb::Error::Other(error::Error::ConnectRefused(
+                types::TcpState::Closed
+            )))
+        ));
+    }
+
+    #[test]
+    fn connect_after_failed_connect() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartClientTcpCmd, &[&[0]]);
+        expect_connect(&mut transport);
+        let mut wifi = Wifi::new(transport);
+
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        assert!(matches!(
+            TcpClientStack::connect(&mut wifi, &mut socket, remote()),
+            Err(nb::Error::Other(error::Error::StartClientByIp))
+        ));
+        // The failed attempt must not keep the firmware socket it allocated
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        TcpClientStack::connect(&mut wifi, &mut socket, remote()).unwrap();
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn receive_would_block_until_peer_closes() {
+        let mut transport = FakeTransport::new();
+        expect_connect(&mut transport)
+            .expect(Command::AvailDataTcpCmd, &[&[0, 0]])
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::Established)]],
+            )
+            .expect(Command::AvailDataTcpCmd, &[&[0, 0]])
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::CloseWait)]],
+            )
+            .expect(Command::AvailDataTcpCmd, &[&[0, 0]]);
+        let mut wifi = Wifi::new(transport);
+
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        TcpClientStack::connect(&mut wifi, &mut socket, remote()).unwrap();
+        let mut buffer = [0; 16];
+        assert!(matches!(
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        ));
+        assert_eq!(
+            0,
+            TcpClientStack::receive(&mut wifi, &mut socket, &mut buffer).unwrap()
+        );
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn accept_reports_each_connection_once() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartServerTcpCmd, &[&[1]])
+            .expect(Command::AvailDataTcpCmd, &[NO_SOCKET])
+            .expect(Command::AvailDataTcpCmd, &[&[1, 0]])
+            .expect(Command::GetRemoteDataCmd, &[&[10, 0, 0, 2], &[0x30, 0x39]])
+            // Still reported while the accepted client has unread data
+            .expect(Command::AvailDataTcpCmd, &[&[1, 0]]);
+        let mut wifi = Wifi::new(transport);
+
+        let mut server = TcpClientStack::socket(&mut wifi).unwrap();
+        TcpFullStack::bind(&mut wifi, &mut server, 80).unwrap();
+        TcpFullStack::listen(&mut wifi, &mut server).unwrap();
+        assert!(matches!(
+            TcpFullStack::accept(&mut wifi, &mut server),
+            Err(nb::Error::WouldBlock)
+        ));
+        let (_client, addr) = TcpFullStack::accept(&mut wifi, &mut server).unwrap();
+        assert_eq!(
+            net::SocketAddr::V4(net::SocketAddrV4::new(
+                net::Ipv4Addr::new(10, 0, 0, 2),
+                12345
+            )),
+            addr
+        );
+        assert!(matches!(
+            TcpFullStack::accept(&mut wifi, &mut server),
+            Err(nb::Error::WouldBlock)
+        ));
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn udp_sockets_are_allocated_lazily() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartServerTcpCmd, &[&[1]])
+            .expect(Command::GetSocketCmd, &[&[1]])
+            .expect(Command::StartServerTcpCmd, &[&[1]]);
+        let mut wifi = Wifi::new(transport);
+
+        let mut first = UdpClientStack::socket(&mut wifi).unwrap();
+        let mut second = UdpClientStack::socket(&mut wifi).unwrap();
+        let mut buffer = [0; 16];
+        assert!(matches!(
+            UdpClientStack::receive(&mut wifi, &mut first, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        ));
+        UdpFullStack::bind(&mut wifi, &mut first, 53).unwrap();
+        UdpFullStack::bind(&mut wifi, &mut second, 54).unwrap();
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn receive_data_that_arrived_with_fin() {
+        let mut transport = FakeTransport::new();
+        expect_connect(&mut transport)
+            .expect(Command::AvailDataTcpCmd, &[&[0, 0]])
+            .expect(
+                Command::GetClientStateTcpCmd,
+                &[&[tcp_state(types::TcpState::CloseWait)]],
+            )
+            .expect(Command::AvailDataTcpCmd, &[&[3, 0]])
+            .expect(Command::GetDatabufTcpCmd, &[b"bye"]);
+        let mut wifi = Wifi::new(transport);
+
+        let mut socket = TcpClientStack::socket(&mut wifi).unwrap();
+        TcpClientStack::connect(&mut wifi, &mut socket, remote()).unwrap();
+        let mut buffer = [0; 16];
+        let len = TcpClientStack::receive(&mut wifi, &mut socket, &mut buffer).unwrap();
+        assert_eq!(b"bye", &buffer[..len]);
+        assert!(wifi.handler.transport().is_done());
+    }
+
+    #[test]
+    fn udp_receive_discards_rest_of_datagram() {
+        let mut transport = FakeTransport::new();
+        transport
+            .expect(Command::GetSocketCmd, &[&[0]])
+            .expect(Command::StartServerTcpCmd, &[&[1]])
+            .expect(Command::AvailDataTcpCmd, &[&[10, 0]])
+            .expect(Command::GetRemoteDataCmd, &[&[10, 0, 0, 2], &[0, 53]])
+            .expect(Command::GetDatabufTcpCmd, &[b"abcd"])
+            .expect(Command::GetDatabufTcpCmd, &[b"efghij"])
+            .expect(Command::AvailDataTcpCmd, &[&[0, 0]]);
+        let mut wifi = Wifi::new(transport);
+
+        let mut socket = UdpClientStack::socket(&mut wifi).unwrap();
+        UdpFullStack::bind(&mut wifi, &mut socket, 53).unwrap();
+        let mut buffer = [0; 4];
+        let (len, _) = UdpClientStack::receive(&mut wifi, &mut socket, &mut buffer).unwrap();
+        assert_eq!(b"abcd", &buffer[..len]);
+        assert!(matches!(
+            UdpClientStack::receive(&mut wifi, &mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        ));
+        assert!(wifi.handler.transport().is_done());
+
+        // The rest is read in one go, not just what fits in the caller's buffer: socket 0, 6 bytes
+        let (command, params) = &wifi.handler.transport().sent[5];
+        assert_eq!(Command::GetDatabufTcpCmd, *command);
+        assert_eq!(&[2, 0, 1, 0, 0, 2, 6, 0][..], &params[..]);
+    }
+}
@@ -183,6 +183,47 @@ where
     }
 }
 
+impl<O> SendParam for Scalar<O, f32>
+where
+    O: byteorder::ByteOrder,
+{
+    fn len(&self) -> usize {
+        4
+    }
+
+    fn send<S>(&self, spi: &mut S) -> Result<(), S::Error>
+    where
+        S: embedded_hal::spi::FullDuplex<u8>,
+    {
+        let mut buf = [0; 4];
+        O::write_f32(&mut buf, self.value);
+        spi.send_exchange(buf[0])?;
+        spi.send_exchange(buf[1])?;
+        spi.send_exchange(buf[2])?;
+        spi.send_exchange(buf[3])?;
+        Ok(())
+    }
+}
+
+impl<O> RecvParam for Scalar<O, f32>
+where
+    O: byteorder::ByteOrder,
+{
+    fn recv<S>(&mut self, spi: &mut S, len: usize) -> Result<(), S::Error>
+    where
+        S: embedded_hal::spi::FullDuplex<u8>,
+    {
+        assert_eq!(4, len);
+        let mut buf = [0; 4];
+        buf[0] = spi.recv_exchange()?;
+        buf[1] = spi.recv_exchange()?;
+        buf[2] = spi.recv_exchange()?;
+        buf[3] = spi.recv_exchange()?;
+        self.value = O::read_f32(&buf);
+        Ok(())
+    }
+}
+
 impl SendParam for [u8] {
     fn len(&self) -> usize {
         self.len()
@@ -217,6 +258,7 @@ where
 }
 
 impl RecvParam for &mut [u8] {
+    #[allow(clippy::mem_replace_with_default, clippy::unnecessary_cast)]
     fn recv<S>(&mut self, spi: &mut S, len: usize) -> Result<(), S::Error>
     where
         S: embedded_hal::spi::FullDuplex<u8>,
@@ -0,0 +1,139 @@
+//! Handles that reach their `Wifi` through a shared `RefCell` instead of an explicit argument.
+
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::Client;
+use crate::Wifi;
+use crate::DEFAULT_BUFFER_CAPACITY;
+use core::cell;
+use core::time;
+
+/// A `Client` bundled with the `Wifi` it belongs to.
+///
+/// Like the `gpio` handles, this only borrows the `Wifi` for the duration of each call, so other
+/// clients and pins can share the same module. Calls fail with `Error::WrongWifi` if the client
+/// was created by a different `Wifi`.
+#[derive(Debug)]
+pub struct SharedClient<'a, T, const N: usize = DEFAULT_BUFFER_CAPACITY> {
+    pub(crate) wifi: &'a cell::RefCell<Wifi<T>>,
+    pub(crate) client: Client<T, N>,
+}
+
+impl<'a, T> SharedClient<'a, T>
+where
+    T: transport::Transport,
+{
+    /// Creates a new client on `wifi`.
+    pub fn create(wifi: &'a cell::RefCell<Wifi<T>>) -> Result<Self, error::Error<T::Error>> {
+        let client = wifi.borrow_mut().new_client()?;
+        Ok(Self::new(wifi, client))
+    }
+}
+
+impl<'a, T, const N: usize> SharedClient<'a, T, N>
+where
+    T: transport::Transport,
+{
+    pub fn new(wifi: &'a cell::RefCell<Wifi<T>>, client: Client<T, N>) -> Self {
+        Self { wifi, client }
+    }
+
+    /// Gives back the client for use with the explicit-borrow API.
+    pub fn into_inner(self) -> Client<T, N> {
+        self.client
+    }
+
+    pub fn wifi(&self) -> &'a cell::RefCell<Wifi<T>> {
+        self.wifi
+    }
+
+    pub fn connect_ipv4(
+        &mut self,
+        ip: no_std_net::Ipv4Addr,
+        port: u16,
+        protocol_mode: types::ProtocolMode,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.client
+            .connect_ipv4(&mut self.wifi.borrow_mut(), ip, port, protocol_mode)
+    }
+
+    pub fn connect_ipv4_timeout(
+        &mut self,
+        ip: no_std_net::Ipv4Addr,
+        port: u16,
+        protocol_mode: types::ProtocolMode,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.client.connect_ipv4_timeout(
+            &mut self.wifi.borrow_mut(),
+            ip,
+            port,
+            protocol_mode,
+            timeout,
+        )
+    }
+
+    pub fn connect_host(
+        &mut self,
+        hostname: &str,
+        port: u16,
+        protocol_mode: types::ProtocolMode,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.client
+            .connect_host(&mut self.wifi.borrow_mut(), hostname, port, protocol_mode)
+    }
+
+    pub fn await_connected(
+        &mut self,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.client
+            .await_connected(&mut self.wifi.borrow_mut(), timeout)
+    }
+
+    pub fn await_tcp_state(
+        &mut self,
+        tcp_state: types::TcpState,
+        timeout: time::Duration,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.client
+            .await_tcp_state(&mut self.wifi.borrow_mut(), tcp_state, timeout)
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> Result<usize, error::Error<T::Error>> {
+        self.client.send(&mut self.wifi.borrow_mut(), data)
+    }
+
+    pub fn send_all(&mut self, data: &[u8]) -> Result<(), error::Error<T::Error>> {
+        self.client.send_all(&mut self.wifi.borrow_mut(), data)
+    }
+
+    pub fn flush(&mut self) -> Result<(), error::Error<T::Error>> {
+        self.client.flush(&mut self.wifi.borrow_mut())
+    }
+
+    pub fn state(&mut self) -> Result<types::TcpState, error::Error<T::Error>> {
+        self.client.state(&mut self.wifi.borrow_mut())
+    }
+
+    pub fn available(&mut self) -> Result<usize, error::Error<T::Error>> {
+        self.client.available(&mut self.wifi.borrow_mut())
+    }
+
+    pub fn recv(&mut self, data: &mut [u8]) -> Result<usize, error::Error<T::Error>> {
+        self.client.recv(&mut self.wifi.borrow_mut(), data)
+    }
+
+    pub fn try_recv(&mut self, data: &mut [u8]) -> nb::Result<usize, error::Error<T::Error>> {
+        self.client.try_recv(&mut self.wifi.borrow_mut(), data)
+    }
+
+    pub fn recv_exact(&mut self, data: &mut [u8]) -> Result<(), error::Error<T::Error>> {
+        self.client.recv_exact(&mut self.wifi.borrow_mut(), data)
+    }
+
+    pub fn close(self, timeout: time::Duration) -> Result<(), error::Error<T::Error>> {
+        self.client.close(&mut self.wifi.borrow_mut(), timeout)
+    }
+}
@@ -0,0 +1,225 @@
+//! `embedded-svc` WiFi support, so provisioning code written for ESP-IDF can drive a NINA module.
+
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::Wifi;
+use core::cell;
+use core::fmt;
+use core::str;
+use core::time;
+use embedded_svc::wifi;
+
+/// How long `disconnect` and `stop` wait for the module to leave the network.
+const DISCONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Adapts a shared `Wifi` to `embedded_svc::wifi::Wifi`.
+///
+/// The trait queries connection state through `&self`, so this borrows the `Wifi` through a
+/// `RefCell` like the `gpio` and `shared` handles do. The module runs either as a client or as an
+/// access point, not both, so `Configuration::Mixed` is rejected. The configuration is stored here
+/// and applied by `connect`, which like on ESP-IDF returns without waiting for the connection to
+/// come up or the access point to start. The BSSID, channel, scan method and PMF settings of a
+/// client configuration and the hidden SSID, protocol and connection limit settings of an access
+/// point configuration are ignored since the firmware has no way to set them.
+///
+/// `embedded_svc::wifi::Wifi::scan` is only implemented with the `std` feature.
+#[derive(Debug)]
+pub struct SvcWifi<'a, T> {
+    wifi: &'a cell::RefCell<Wifi<T>>,
+    configuration: wifi::Configuration,
+    started: bool,
+}
+
+impl<'a, T> SvcWifi<'a, T>
+where
+    T: transport::Transport,
+{
+    pub fn new(wifi: &'a cell::RefCell<Wifi<T>>) -> Self {
+        let configuration = wifi::Configuration::None;
+        let started = false;
+        Self {
+            wifi,
+            configuration,
+            started,
+        }
+    }
+
+    pub fn wifi(&self) -> &'a cell::RefCell<Wifi<T>> {
+        self.wifi
+    }
+
+    fn scan_with(
+        &mut self,
+        mut f: impl FnMut(wifi::AccessPointInfo),
+    ) -> Result<(), error::Error<T::Error>> {
+        let mut wifi = self.wifi.borrow_mut();
+        for network in wifi.scan_networks()? {
+            f(access_point_info(network?));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T> wifi::Wifi for SvcWifi<'a, T>
+where
+    T: transport::Transport,
+    T::Error: fmt::Debug,
+{
+    type Error = error::Error<T::Error>;
+
+    fn get_capabilities(&self) -> Result<enumset::EnumSet<wifi::Capability>, Self::Error> {
+        Ok(wifi::Capability::Client | wifi::Capability::AccessPoint)
+    }
+
+    fn get_configuration(&self) -> Result<wifi::Configuration, Self::Error> {
+        Ok(self.configuration.clone())
+    }
+
+    fn set_configuration(&mut self, conf: &wifi::Configuration) -> Result<(), Self::Error> {
+        match conf {
+            wifi::Configuration::None => {}
+            wifi::Configuration::Client(client) => {
+                network_config(client)?;
+            }
+            wifi::Configuration::AccessPoint(access_point) => {
+                access_point_config(access_point)?;
+            }
+            wifi::Configuration::Mixed(_, _) => return Err(error::Error::MixedModeUnsupported),
+        }
+        self.configuration = conf.clone();
+        Ok(())
+    }
+
+    /// The module's radio is always on, so this only records that the interface is in use.
+    fn start(&mut self) -> Result<(), Self::Error> {
+        self.started = true;
+        Ok(())
+    }
+
+    /// Leaves the network or shuts down the access point.
+    fn stop(&mut self) -> Result<(), Self::Error> {
+        self.disconnect()?;
+        self.started = false;
+        Ok(())
+    }
+
+    /// Joins the configured network, or starts the configured access point.
+    fn connect(&mut self) -> Result<(), Self::Error> {
+        let config = match &self.configuration {
+            wifi::Configuration::Client(client) => {
+                let network = network_config(client)?;
+                types::Config::Station(types::StationConfig { network })
+            }
+            wifi::Configuration::AccessPoint(access_point) => {
+                types::Config::AccessPoint(access_point_config(access_point)?)
+            }
+            wifi::Configuration::None | wifi::Configuration::Mixed(_, _) => {
+                return Err(error::Error::NotConfigured)
+            }
+        };
+        self.wifi.borrow_mut().configure(config, None)
+    }
+
+    fn disconnect(&mut self) -> Result<(), Self::Error> {
+        self.wifi.borrow_mut().disconnect(DISCONNECT_TIMEOUT)
+    }
+
+    fn is_started(&self) -> Result<bool, Self::Error> {
+        Ok(self.started)
+    }
+
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        let state = self.wifi.borrow_mut().connection_state()?;
+        Ok(state == types::ConnectionState::Connected)
+    }
+
+    fn scan_n<const N: usize>(
+        &mut self,
+    ) -> Result<(heapless::Vec<wifi::AccessPointInfo, N>, usize), Self::Error> {
+        let mut result = heapless::Vec::new();
+        let mut total = 0;
+        self.scan_with(|info| {
+            total += 1;
+            let _ = result.push(info);
+        })?;
+        Ok((result, total))
+    }
+
+    #[cfg(feature = "std")]
+    fn scan(&mut self) -> Result<std::vec::Vec<wifi::AccessPointInfo>, Self::Error> {
+        let mut result = std::vec::Vec::new();
+        self.scan_with(|info| result.push(info))?;
+        Ok(result)
+    }
+}
+
+fn network_config<E>(
+    client: &wifi::ClientConfiguration,
+) -> Result<types::NetworkConfig<'_>, error::Error<E>> {
+    let ssid = client.ssid.as_bytes();
+    match client.auth_method {
+        wifi::AuthMethod::None => Ok(types::NetworkConfig::Open { ssid }),
+        wifi::AuthMethod::WPA
+        | wifi::AuthMethod::WPA2Personal
+        | wifi::AuthMethod::WPAWPA2Personal
+        | wifi::AuthMethod::WPA3Personal
+        | wifi::AuthMethod::WPA2WPA3Personal => Ok(types::NetworkConfig::Password {
+            ssid,
+            password: client.password.as_bytes(),
+        }),
+        wifi::AuthMethod::WEP
+        | wifi::AuthMethod::WPA2Enterprise
+        | wifi::AuthMethod::WAPIPersonal => Err(error::Error::AuthMethodUnsupported),
+    }
+}
+
+fn access_point_config<E>(
+    access_point: &wifi::AccessPointConfiguration,
+) -> Result<types::AccessPointConfig<'_>, error::Error<E>> {
+    // The firmware always sets up WPA/WPA2 mixed mode when given a passphrase
+    let password = match access_point.auth_method {
+        wifi::AuthMethod::None => &[][..],
+        wifi::AuthMethod::WPA
+        | wifi::AuthMethod::WPA2Personal
+        | wifi::AuthMethod::WPAWPA2Personal => access_point.password.as_bytes(),
+        wifi::AuthMethod::WEP
+        | wifi::AuthMethod::WPA2Enterprise
+        | wifi::AuthMethod::WPA3Personal
+        | wifi::AuthMethod::WPA2WPA3Personal
+        | wifi::AuthMethod::WAPIPersonal => return Err(error::Error::AuthMethodUnsupported),
+    };
+    Ok(types::AccessPointConfig {
+        ssid: access_point.ssid.as_bytes(),
+        password,
+        channel: access_point.channel,
+    })
+}
+
+fn access_point_info(network: types::ScannedNetwork) -> wifi::AccessPointInfo {
+    let mut ssid = heapless::String::new();
+    // Both hold at most 32 bytes; SSIDs that aren't UTF-8 are left empty
+    if let Ok(name) = str::from_utf8(&network.ssid) {
+        let _ = ssid.push_str(name);
+    }
+
+    let auth_method = match network.encryption_type {
+        types::EncryptionType::OpenSystem => Some(wifi::AuthMethod::None),
+        types::EncryptionType::SharedKey => Some(wifi::AuthMethod::WEP),
+        types::EncryptionType::Wpa | types::EncryptionType::WpaPsk => Some(wifi::AuthMethod::WPA),
+        types::EncryptionType::Wpa2 | types::EncryptionType::Wpa2Psk => {
+            Some(wifi::AuthMethod::WPA2Personal)
+        }
+        types::EncryptionType::Auto | types::EncryptionType::Invalid => None,
+    };
+
+    wifi::AccessPointInfo {
+        ssid,
+        bssid: network.bssid,
+        channel: network.channel,
+        secondary_channel: wifi::SecondaryChannel::None,
+        signal_strength: network.rssi.clamp(i32::from(i8::MIN), i32::from(i8::MAX)) as i8,
+        protocols: enumset::EnumSet::empty(),
+        auth_method,
+    }
+}
@@ -0,0 +1,127 @@
+//! A scripted `Transport` for unit tests.
+
+extern crate std;
+
+use crate::command;
+use crate::params;
+use crate::transport;
+use core::convert::Infallible;
+use core::fmt;
+use core::time;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+/// Replies to each command with the next scripted response, in order.
+///
+/// Commands that don't match the script, or that run past its end, fail the test.
+#[derive(Debug, Default)]
+pub struct FakeTransport {
+    script: VecDeque<(command::Command, Vec<Vec<u8>>)>,
+    /// The raw parameter bytes sent with each command.
+    pub sent: Vec<(command::Command, Vec<u8>)>,
+    pub resets: usize,
+    /// The total time passed to `delay`.
+    pub elapsed: time::Duration,
+}
+
+impl FakeTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects `command` next and replies with `params`.
+    pub fn expect(&mut self, command: command::Command, params: &[&[u8]]) -> &mut Self {
+        let params = params.iter().map(|param| param.to_vec()).collect();
+        self.script.push_back((command, params));
+        self
+    }
+
+    /// Whether every scripted command has been issued.
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty()
+    }
+}
+
+impl transport::Transport for FakeTransport {
+    type Error = Infallible;
+
+    fn reset(&mut self) -> Result<(), Self::Error> {
+        self.resets += 1;
+        Ok(())
+    }
+
+    fn delay(&mut self, duration: time::Duration) -> Result<(), Self::Error> {
+        self.elapsed += duration;
+        Ok(())
+    }
+
+    fn handle_cmd<SP, RP>(
+        &mut self,
+        command: command::Command,
+        send_params: &SP,
+        recv_params: &mut RP,
+        long_send: bool,
+        long_recv: bool,
+    ) -> Result<(), Self::Error>
+    where
+        SP: params::SendParams + fmt::Debug,
+        RP: params::RecvParams + fmt::Debug,
+    {
+        let (expected, reply) = self
+            .script
+            .pop_front()
+            .unwrap_or_else(|| panic!("unexpected {:?} {:?}", command, send_params));
+        assert_eq!(expected, command, "sent {:?}", send_params);
+
+        let mut spi = FakeSpi::default();
+        send_params.send(&mut spi, long_send).unwrap();
+        self.sent.push((command, spi.sent));
+
+        let mut bytes = VecDeque::new();
+        bytes.push_back(reply.len() as u8);
+        for param in reply {
+            if long_recv {
+                bytes.extend(&(param.len() as u16).to_be_bytes());
+            } else {
+                bytes.push_back(param.len() as u8);
+            }
+            bytes.extend(param);
+        }
+        let mut spi = FakeSpi {
+            reply: bytes,
+            receiving: true,
+            ..FakeSpi::default()
+        };
+        recv_params.recv(&mut spi, long_recv).unwrap();
+        assert!(spi.reply.is_empty(), "unread reply to {:?}", command);
+        Ok(())
+    }
+}
+
+/// Records the bytes written and answers reads from `reply`.
+#[derive(Debug, Default)]
+struct FakeSpi {
+    sent: Vec<u8>,
+    reply: VecDeque<u8>,
+    receiving: bool,
+}
+
+impl embedded_hal::spi::FullDuplex<u8> for FakeSpi {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if self.receiving {
+            Ok(self.reply.pop_front().expect("reply too short"))
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn send(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        // While receiving, the words sent only clock out the reply
+        if !self.receiving {
+            self.sent.push(word);
+        }
+        Ok(())
+    }
+}
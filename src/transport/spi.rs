@@ -101,6 +101,7 @@ where
     }
 }
 
+#[allow(clippy::type_complexity)]
 impl<SPI, BUSY, RESET, CS, DELAY> SpiTransport<SPI, BUSY, RESET, CS, DELAY>
 where
     SPI: embedded_hal::spi::FullDuplex<u8>,
@@ -1,4 +1,5 @@
 use core::fmt;
+use core::time;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Config<'a> {
@@ -21,12 +22,30 @@ pub enum NetworkConfig<'a> {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AccessPointConfig<'a> {
     pub ssid: &'a [u8],
+    /// Empty for an open network; otherwise secured with WPA/WPA2.
     pub password: &'a [u8],
+    pub channel: u8,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Socket(pub(crate) u8);
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SocketKind {
+    TcpClient,
+    TcpServer,
+    Udp,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SocketInfo {
+    pub socket: Socket,
+    pub kind: SocketKind,
+    pub state: TcpState,
+    /// The peer of a client or UDP socket; `None` for servers.
+    pub remote: Option<RemoteData>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ScannedNetwork {
     pub ssid: arrayvec::ArrayVec<[u8; 32]>,
@@ -44,6 +63,15 @@ pub enum PinMode {
     InputPullup = 2,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive)]
+#[repr(u8)]
+pub enum AdcAttenuation {
+    Db0 = 0,
+    Db2_5 = 1,
+    Db6 = 2,
+    Db11 = 3,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive)]
 #[repr(u8)]
 pub enum ProtocolMode {
@@ -68,6 +96,62 @@ pub enum ConnectionState {
     ApFailed = 9,
 }
 
+/// Why the station last left (or failed to join) a network, as an ESP-IDF `wifi_err_reason_t`.
+///
+/// Codes that newer ESP-IDF versions may add are kept as `Other`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, num_enum::IntoPrimitive, num_enum::FromPrimitive)]
+#[repr(u8)]
+pub enum DisconnectReason {
+    Unspecified = 1,
+    AuthExpire = 2,
+    AuthLeave = 3,
+    AssocExpire = 4,
+    AssocTooMany = 5,
+    NotAuthed = 6,
+    NotAssoced = 7,
+    AssocLeave = 8,
+    AssocNotAuthed = 9,
+    DisassocPwrcapBad = 10,
+    DisassocSupchanBad = 11,
+    IeInvalid = 13,
+    MicFailure = 14,
+    FourWayHandshakeTimeout = 15,
+    GroupKeyUpdateTimeout = 16,
+    IeIn4WayDiffers = 17,
+    GroupCipherInvalid = 18,
+    PairwiseCipherInvalid = 19,
+    AkmpInvalid = 20,
+    UnsuppRsnIeVersion = 21,
+    InvalidRsnIeCap = 22,
+    Ieee8021xAuthFailed = 23,
+    CipherSuiteRejected = 24,
+    TdlsPeerUnreachable = 25,
+    TdlsUnspecified = 26,
+    SspRequestedDisassoc = 27,
+    NoSspRoamingAgreement = 28,
+    BadCipherOrAkm = 29,
+    NotAuthorizedThisLocation = 30,
+    ServiceChangePrecludesTs = 31,
+    UnspecifiedQos = 32,
+    NotEnoughBandwidth = 33,
+    MissingAcks = 34,
+    BeaconTimeout = 200,
+    NoApFound = 201,
+    AuthFail = 202,
+    AssocFail = 203,
+    HandshakeTimeout = 204,
+    ConnectionFail = 205,
+    ApTsfReset = 206,
+    Roaming = 207,
+    AssocComebackTimeTooLong = 208,
+    SaQueryTimeout = 209,
+    NoApFoundWithCompatibleSecurity = 210,
+    NoApFoundInAuthmodeThreshold = 211,
+    NoApFoundInRssiThreshold = 212,
+    #[num_enum(catch_all)]
+    Other(u8),
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive)]
 #[repr(u8)]
 pub enum TcpState {
@@ -84,6 +168,15 @@ pub enum TcpState {
     TimeWait = 10,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive)]
+#[repr(i16)]
+pub enum PingFailure {
+    DestinationUnreachable = -1,
+    Timeout = -2,
+    UnknownHost = -3,
+    Error = -4,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, num_enum::IntoPrimitive, num_enum::TryFromPrimitive)]
 #[repr(u8)]
 pub enum EncryptionType {
@@ -107,9 +200,60 @@ pub struct NetworkData {
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct RemoteData {
     pub ip: no_std_net::Ipv4Addr,
-    pub port: u32,
+    pub port: u16,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PingStats {
+    pub sent: u16,
+    pub received: u16,
+    pub min: time::Duration,
+    pub avg: time::Duration,
+    pub max: time::Duration,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
 }
 
+/// The NINA firmware forks, which added different commands on top of the same base.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FirmwareVendor {
+    /// `arduino/nina-fw`, as shipped on Arduino boards.
+    Arduino,
+    /// `adafruit/nina-fw`, as shipped on AirLift boards.
+    Adafruit,
+}
+
+/// Optional firmware features, gated on the fork and version that introduced them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Capability {
+    Ping,
+    HostnameConnect,
+    Temperature,
+    FirmwareDebug,
+    DigitalRead,
+    AnalogRead,
+    DisconnectReason,
+    ClientCertificate,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub firmware_version: arrayvec::ArrayVec<[u8; 16]>,
+    pub mac_address: [u8; 6],
+    pub connection_state: ConnectionState,
+    pub temperature: Option<Temperature>,
+    /// The last debug output setting acknowledged by the firmware, if one was ever sent.
+    pub firmware_debug: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Temperature(f32);
+
 impl fmt::Display for ScannedNetwork {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use itertools::Itertools;
@@ -152,3 +296,185 @@ impl fmt::Display for EncryptionType {
         f.pad(string)
     }
 }
+
+impl Socket {
+    /// The firmware's number for this socket.
+    pub fn number(self) -> u8 {
+        self.0
+    }
+}
+
+impl PingStats {
+    pub fn lost(&self) -> u16 {
+        self.sent - self.received
+    }
+
+    /// The fraction of probes that got no reply, in percent.
+    pub fn loss_percent(&self) -> u8 {
+        if self.sent == 0 {
+            0
+        } else {
+            (u32::from(self.lost()) * 100 / u32::from(self.sent)) as u8
+        }
+    }
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses a version string like `1.4.8` as reported by `GetFwVersionCmd`.
+    ///
+    /// Missing trailing components default to zero; anything after the patch number (like
+    /// `-rc1`) is ignored.
+    pub fn parse(version: &[u8]) -> Option<Self> {
+        let mut parts = version.split(|&b| b == b'.');
+        let major = parse_version_part(parts.next()?)?;
+        let minor = parts.next().map_or(Some(0), parse_version_part)?;
+        let patch = parts.next().map_or(Some(0), parse_version_part)?;
+        Some(Self::new(major, minor, patch))
+    }
+
+    /// Whether this version of `vendor`'s firmware supports `capability`.
+    ///
+    /// If the vendor isn't known, both forks must support it.
+    pub fn supports(self, capability: Capability, vendor: Option<FirmwareVendor>) -> bool {
+        let supported_by = |vendor| {
+            capability
+                .min_version(vendor)
+                .map_or(false, |min_version| self >= min_version)
+        };
+        match vendor {
+            Some(vendor) => supported_by(vendor),
+            None => supported_by(FirmwareVendor::Arduino) && supported_by(FirmwareVendor::Adafruit),
+        }
+    }
+}
+
+impl Capability {
+    /// The first version of `vendor`'s firmware with this capability, or `None` if that fork
+    /// doesn't have it at all.
+    pub fn min_version(self, vendor: FirmwareVendor) -> Option<FirmwareVersion> {
+        use FirmwareVendor::{Adafruit, Arduino};
+
+        match (self, vendor) {
+            (Capability::Ping, _) => Some(FirmwareVersion::new(1, 1, 0)),
+            (Capability::FirmwareDebug, _) => Some(FirmwareVersion::new(1, 1, 0)),
+            (Capability::HostnameConnect, _) => Some(FirmwareVersion::new(1, 2, 0)),
+            (Capability::Temperature, _) => Some(FirmwareVersion::new(1, 3, 0)),
+            // GetReasonCodeCmd (0x1F) and the client certificate commands (0x40, 0x41)
+            (Capability::ClientCertificate, Arduino) => Some(FirmwareVersion::new(1, 4, 0)),
+            (Capability::DisconnectReason, Arduino) => Some(FirmwareVersion::new(1, 4, 5)),
+            (Capability::ClientCertificate, Adafruit)
+            | (Capability::DisconnectReason, Adafruit) => None,
+            // GetDigitalReadCmd (0x53) and GetAnalogReadCmd (0x54)
+            (Capability::DigitalRead, Adafruit) | (Capability::AnalogRead, Adafruit) => {
+                Some(FirmwareVersion::new(1, 4, 0))
+            }
+            (Capability::DigitalRead, Arduino) | (Capability::AnalogRead, Arduino) => None,
+        }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Temperature {
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self(celsius)
+    }
+
+    pub fn celsius(self) -> f32 {
+        self.0
+    }
+
+    pub fn fahrenheit(self) -> f32 {
+        self.0 * 1.8 + 32.0
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1}°C", self.0)
+    }
+}
+
+fn parse_version_part(part: &[u8]) -> Option<u8> {
+    let digits = part.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    core::str::from_utf8(&part[..digits]).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_firmware_version() {
+        assert_eq!(
+            Some(FirmwareVersion::new(1, 4, 8)),
+            FirmwareVersion::parse(b"1.4.8")
+        );
+        assert_eq!(
+            Some(FirmwareVersion::new(1, 7, 0)),
+            FirmwareVersion::parse(b"1.7")
+        );
+        assert_eq!(
+            Some(FirmwareVersion::new(2, 0, 0)),
+            FirmwareVersion::parse(b"2")
+        );
+        assert_eq!(
+            Some(FirmwareVersion::new(1, 5, 0)),
+            FirmwareVersion::parse(b"1.5.0-rc1")
+        );
+        assert_eq!(
+            Some(FirmwareVersion::new(1, 2, 3)),
+            FirmwareVersion::parse(b"1.2.3\0")
+        );
+    }
+
+    #[test]
+    fn parse_bad_firmware_version() {
+        assert_eq!(None, FirmwareVersion::parse(b""));
+        assert_eq!(None, FirmwareVersion::parse(b"v1.4.8"));
+        assert_eq!(None, FirmwareVersion::parse(b"1..8"));
+        assert_eq!(None, FirmwareVersion::parse(b"1.x"));
+        assert_eq!(None, FirmwareVersion::parse(b"256.0.0"));
+    }
+
+    #[test]
+    fn capabilities_depend_on_vendor() {
+        let version = FirmwareVersion::new(1, 4, 8);
+        let arduino = Some(FirmwareVendor::Arduino);
+        let adafruit = Some(FirmwareVendor::Adafruit);
+
+        assert!(version.supports(Capability::DisconnectReason, arduino));
+        assert!(!version.supports(Capability::DisconnectReason, adafruit));
+        assert!(!version.supports(Capability::DigitalRead, arduino));
+        assert!(version.supports(Capability::DigitalRead, adafruit));
+        assert!(version.supports(Capability::Temperature, None));
+        assert!(!version.supports(Capability::DisconnectReason, None));
+        assert!(!FirmwareVersion::new(1, 0, 0).supports(Capability::Ping, arduino));
+    }
+
+    #[test]
+    fn disconnect_reason_keeps_unknown_codes() {
+        assert_eq!(DisconnectReason::MissingAcks, DisconnectReason::from(34));
+        assert_eq!(
+            DisconnectReason::NoApFoundInRssiThreshold,
+            DisconnectReason::from(212)
+        );
+        assert_eq!(DisconnectReason::Other(12), DisconnectReason::from(12));
+        assert_eq!(250u8, u8::from(DisconnectReason::Other(250)));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error;
+use crate::transport;
+use crate::types;
+use crate::Wifi;
+use crate::MAX_TRANSFER_LEN;
+use core::marker;
+
+#[derive(Debug)]
+pub struct UdpSocket<T> {
+    socket: types::Socket,
+    wifi_id: usize,
+    generation: u32,
+    started: bool,
+    local_port: Option<u16>,
+    remote: Option<types::RemoteData>,
+    group: Option<types::RemoteData>,
+    phantom: marker::PhantomData<T>,
+}
+
+impl<T> UdpSocket<T>
+where
+    T: transport::Transport,
+{
+    pub(crate) fn new(socket: types::Socket, wifi_id: usize, generation: u32) -> Self {
+        let started = false;
+        let local_port = None;
+        let remote = None;
+        let group = None;
+        let phantom = marker::PhantomData;
+        Self {
+            socket,
+            wifi_id,
+            generation,
+            started,
+            local_port,
+            remote,
+            group,
+            phantom,
+        }
+    }
+
+    pub fn local_port(&self) -> Option<u16> {
+        self.local_port
+    }
+
+    /// Starts receiving datagrams sent to `port`.
+    pub fn bind(&mut self, wifi: &mut Wifi<T>, port: u16) -> Result<(), error::Error<T::Error>> {
+        self.check(wifi)?;
+        let socket = self.socket;
+        wifi.start_socket(socket, &mut self.started, |handler| {
+            handler.start_server(port, socket, types::ProtocolMode::Udp)
+        })?;
+        self.local_port = Some(port);
+        Ok(())
+    }
+
+    /// Joins a multicast group and starts receiving datagrams sent to it on `port`.
+    pub fn join_multicast(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        group: no_std_net::Ipv4Addr,
+        port: u16,
+    ) -> Result<(), error::Error<T::Error>> {
+        if !group.is_multicast() {
+            return Err(error::Error::NotMulticast(group));
+        }
+        self.check(wifi)?;
+        let socket = self.socket;
+        wifi.start_socket(socket, &mut self.started, |handler| {
+            handler.start_multicast(group, port, socket)
+        })?;
+        self.local_port = Some(port);
+        self.group = Some(types::RemoteData { ip: group, port });
+        Ok(())
+    }
+
+    /// The multicast group and port joined with `join_multicast`.
+    pub fn group(&self) -> Option<types::RemoteData> {
+        self.group
+    }
+
+    pub fn send_to_group(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        data: &[u8],
+    ) -> Result<(), error::Error<T::Error>> {
+        let group = self.group.ok_or(error::Error::NotConnected)?;
+        self.send_to(wifi, data, group.ip, group.port)
+    }
+
+    /// Sets the default destination for `send`; this doesn't talk to the module.
+    pub fn connect(&mut self, ip: no_std_net::Ipv4Addr, port: u16) {
+        self.remote = Some(types::RemoteData { ip, port });
+    }
+
+    pub fn send(&mut self, wifi: &mut Wifi<T>, data: &[u8]) -> Result<(), error::Error<T::Error>> {
+        let remote = self.remote.ok_or(error::Error::NotConnected)?;
+        self.send_to(wifi, data, remote.ip, remote.port)
+    }
+
+    /// Sends one datagram.
+    ///
+    /// The payload is copied into the firmware's packet buffer in as many transfers as needed and
+    /// then sent as a whole.
+    pub fn send_to(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        data: &[u8],
+        ip: no_std_net::Ipv4Addr,
+        port: u16,
+    ) -> Result<(), error::Error<T::Error>> {
+        self.check(wifi)?;
+        let socket = self.socket;
+        wifi.start_socket(socket, &mut self.started, |handler| {
+            handler.start_client_by_ip(ip, port, socket, types::ProtocolMode::Udp)
+        })?;
+        for chunk in data.chunks(MAX_TRANSFER_LEN) {
+            wifi.handler.insert_data_buf(self.socket, chunk)?;
+        }
+        wifi.handler.send_udp_data(self.socket)
+    }
+
+    /// Receives one datagram, or returns `WouldBlock` if none is waiting.
+    ///
+    /// Like `std::net::UdpSocket::recv_from`, the part of a datagram that doesn't fit in `data`
+    /// is discarded, so the next call always starts at a datagram boundary.
+    pub fn recv_from(
+        &mut self,
+        wifi: &mut Wifi<T>,
+        data: &mut [u8],
+    ) -> nb::Result<(usize, types::RemoteData), error::Error<T::Error>> {
+        self.check(wifi)?;
+
+        let mut remaining = usize::from(wifi.handler.avail_data(self.socket)?);
+        if remaining == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let remote = wifi.handler.get_remote_data(self.socket)?;
+
+        let mut len = 0;
+        while remaining > 0 && len < data.len() {
+            let end = data.len().min(len + remaining.min(MAX_TRANSFER_LEN));
+            let recv_len = wifi
+                .handler
+                .get_data_buf(self.socket, &mut data[len..end])?;
+            if recv_len == 0 {
+                break;
+            }
+            len += recv_len;
+            remaining -= recv_len.min(remaining);
+        }
+
+        if remaining > 0 {
+            log::debug!("discarding {} bytes of oversized datagram", remaining);
+            let mut scratch = [0; 256];
+            while remaining > 0 {
+                let end = remaining.min(scratch.len());
+                let recv_len = wifi
+                    .handler
+                    .get_data_buf(self.socket, &mut scratch[..end])?;
+                if recv_len == 0 {
+                    break;
+                }
+                remaining -= recv_len.min(remaining);
+            }
+        }
+
+        Ok((len, remote))
+    }
+
+    pub fn close(self, wifi: &mut Wifi<T>) -> Result<(), error::Error<T::Error>> {
+        match self.check(wifi) {
+            Ok(()) => {}
+            Err(error::Error::ClientInvalidated) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        if self.started {
+            // Nothing could release the socket after this, so don't keep it on failure
+            let result = wifi.handler.stop_client(self.socket);
+            wifi.release_socket(self.socket);
+            result
+        } else {
+            wifi.release_unstarted_socket(self.socket);
+            Ok(())
+        }
+    }
+
+    fn check(&self, wifi: &Wifi<T>) -> Result<(), error::Error<T::Error>> {
+        wifi.check_handle(self.wifi_id, self.generation)
+    }
+}